@@ -16,10 +16,160 @@ mod gen_delimited_bytes;
 mod gen_dynamic_bytes;
 mod gen_remaining_bytes;
 mod gen_dynamic_array;
+mod gen_signed_len;
 mod gen_enum;
 mod gen_enum_default;
 mod gen_enum_external_deps;
 mod gen_string;
+mod gen_optional;
+mod gen_conditional;
+mod gen_optional_outer;
+mod gen_trailing;
+mod gen_int_transform;
+mod gen_custom_len;
+mod gen_fan_out;
+mod gen_fan_out_enum;
+mod gen_checksum;
+mod gen_capture;
+mod gen_decode;
+mod gen_decode_codecs;
+mod gen_framed;
+mod gen_delimited_ext;
+mod gen_delimited_lf;
+mod gen_tlv;
+mod gen_stride_array;
+mod gen_dynamic_map;
+mod gen_previous;
+mod gen_array_external;
+mod gen_array_index;
+mod gen_type_def;
+mod gen_type_def_params;
+mod gen_type_def_recursive;
+mod gen_type_fns;
+mod gen_delegate;
+mod gen_plugin;
+mod gen_custom_write_err;
+
+/// Hand-written types for testing `Scope::delegate`.
+mod varint {
+    use futures::{
+        AsyncReadExt,
+        AsyncWriteExt,
+    };
+    use inarybay_runtime::traits::{
+        AsyncDelegate,
+        Delegate,
+    };
+
+    /// LEB128-encoded unsigned integer
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct VarInt(pub u64);
+
+    impl VarInt {
+        fn encode(&self) -> Vec<u8> {
+            let mut out = vec![];
+            let mut v = self.0;
+            loop {
+                let low = (v & 0x7f) as u8;
+                v >>= 7;
+                if v == 0 {
+                    out.push(low);
+                    return out;
+                }
+                out.push(low | 0x80);
+            }
+        }
+
+        fn decode_byte(&mut self, shift: &mut u32, b: u8) -> std::io::Result<bool> {
+            if *shift >= 64 {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "VarInt too long"));
+            }
+            self.0 |= ((b & 0x7f) as u64) << *shift;
+            *shift += 7;
+            return Ok(b & 0x80 == 0);
+        }
+    }
+
+    impl Delegate for VarInt {
+        fn read<R: std::io::Read>(source: &mut R, _offset: usize) -> std::io::Result<Self> {
+            let mut out = VarInt(0);
+            let mut shift = 0;
+            loop {
+                let mut b = [0u8];
+                source.read_exact(&mut b)?;
+                if out.decode_byte(&mut shift, b[0])? {
+                    return Ok(out);
+                }
+            }
+        }
+
+        fn write<W: std::io::Write>(self, dest: &mut W, _offset: Option<usize>) -> std::io::Result<()> {
+            return dest.write_all(&self.encode());
+        }
+    }
+
+    impl AsyncDelegate for VarInt {
+        async fn read_async<
+            R: inarybay_runtime::async_::AsyncRead + Unpin,
+        >(source: &mut R, _offset: usize) -> std::io::Result<Self> {
+            let mut out = VarInt(0);
+            let mut shift = 0;
+            loop {
+                let mut b = [0u8];
+                source.read_exact(&mut b).await?;
+                if out.decode_byte(&mut shift, b[0])? {
+                    return Ok(out);
+                }
+            }
+        }
+
+        async fn write_async<
+            W: inarybay_runtime::async_::AsyncWrite + Unpin,
+        >(self, dest: &mut W, _offset: Option<usize>) -> std::io::Result<()> {
+            return dest.write_all(&self.encode()).await;
+        }
+    }
+
+    /// Takes up no bytes, reads as the offset it was read at.  Writing fails unless
+    /// it's written at the same offset.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct ReadOffset(pub usize);
+
+    impl ReadOffset {
+        fn check_offset(&self, offset: Option<usize>) -> std::io::Result<()> {
+            if offset != Some(self.0) {
+                return Err(
+                    std::io::Error::other(format!("Expected to write at offset {}, got {:?}", self.0, offset)),
+                );
+            }
+            return Ok(());
+        }
+    }
+
+    impl Delegate for ReadOffset {
+        fn read<R: std::io::Read>(_source: &mut R, offset: usize) -> std::io::Result<Self> {
+            return Ok(ReadOffset(offset));
+        }
+
+        fn write<W: std::io::Write>(self, _dest: &mut W, offset: Option<usize>) -> std::io::Result<()> {
+            return self.check_offset(offset);
+        }
+    }
+
+    impl AsyncDelegate for ReadOffset {
+        async fn read_async<
+            R: inarybay_runtime::async_::AsyncRead + Unpin,
+        >(_source: &mut R, offset: usize) -> std::io::Result<Self> {
+            return Ok(ReadOffset(offset));
+        }
+
+        async fn write_async<
+            W: inarybay_runtime::async_::AsyncWrite + Unpin,
+        >(self, _dest: &mut W, offset: Option<usize>) -> std::io::Result<()> {
+            return self.check_offset(offset);
+        }
+    }
+}
 
 macro_rules! round_trip{
     ($test: ident, $asynctest: ident; $mod: ident, $e: expr) => {
@@ -194,6 +344,35 @@ round_trip!(
     gen_dynamic_array::T1 { thrusters: vec![gen_dynamic_array::Thrusters { f: 7 }] }
 );
 
+#[test]
+fn test_dynamic_array_err_index() {
+    let bytes = vec![2u8, 7];
+    let err = gen_dynamic_array::read(&mut std::io::Cursor::new(&bytes)).unwrap_err();
+    assert_eq!(err.index, Some(1));
+    assert!(err.to_string().contains("(element 1)"));
+}
+
+round_trip!(
+    test_signed_len,
+    test_signed_len_async;
+    gen_signed_len,
+    gen_signed_len::T1 {
+        data: vec![4u8, 5u8],
+        items: vec![6u8],
+    },
+    [2u8, 4u8, 5u8, 1u8, 6u8]
+);
+
+#[test]
+fn test_signed_len_negative() {
+    let bytes = vec![0xFFu8];
+    let err = gen_signed_len::read(&mut std::io::Cursor::new(&bytes)).unwrap_err();
+    assert_eq!(err.node, "data_val");
+    let bytes = vec![1u8, 9u8, 0xFFu8];
+    let err = gen_signed_len::read(&mut std::io::Cursor::new(&bytes)).unwrap_err();
+    assert_eq!(err.node, "items_val");
+}
+
 round_trip!(
     test_enum,
     test_enum_async;
@@ -223,3 +402,1041 @@ round_trip!(
     gen_string,
     gen_string::T1 { g: "the last test".to_string() }
 );
+
+round_trip!(
+    test_optional_some,
+    test_optional_some_async;
+    gen_optional,
+    gen_optional::T1 {
+        other: 9,
+        stamp: Some(70000),
+    },
+    [0b0001_0011u8, 112u8, 17u8, 1u8, 0u8]
+);
+
+round_trip!(
+    test_optional_none,
+    test_optional_none_async;
+    gen_optional,
+    gen_optional::T1 {
+        other: 9,
+        stamp: None,
+    },
+    [0b0001_0010u8]
+);
+
+round_trip!(
+    test_conditional_present,
+    test_conditional_present_async;
+    gen_conditional,
+    gen_conditional::T1 {
+        version: 3,
+        kind: 7,
+        extra: Some(300),
+    },
+    [3u8, 0u8, 7u8, 44u8, 1u8]
+);
+
+round_trip!(
+    test_conditional_absent,
+    test_conditional_absent_async;
+    gen_conditional,
+    gen_conditional::T1 {
+        version: 2,
+        kind: 7,
+        extra: None,
+    },
+    [2u8, 0u8, 7u8]
+);
+
+#[test]
+fn test_conditional_mismatch() {
+    let mut bytes = vec![];
+    assert!(gen_conditional::write(&mut bytes, gen_conditional::T1 {
+        version: 2,
+        kind: 7,
+        extra: Some(300),
+    }).is_err());
+    assert!(gen_conditional::write(&mut bytes, gen_conditional::T1 {
+        version: 3,
+        kind: 7,
+        extra: None,
+    }).is_err());
+}
+
+round_trip!(
+    test_optional_outer,
+    test_optional_outer_async;
+    gen_optional_outer,
+    gen_optional_outer::T1 {
+        len: 2,
+        payload: Some(vec![4u8, 5u8]),
+        extra: Some(vec![6u8, 7u8]),
+    },
+    [2u8, 1u8, 4u8, 5u8, 6u8, 7u8]
+);
+
+round_trip!(
+    test_optional_outer_none,
+    test_optional_outer_none_async;
+    gen_optional_outer,
+    gen_optional_outer::T1 {
+        len: 1,
+        payload: None,
+        extra: None,
+    },
+    [1u8, 0u8]
+);
+
+#[test]
+fn test_optional_outer_mismatch() {
+    let mut bytes = vec![];
+    assert!(gen_optional_outer::write(&mut bytes, gen_optional_outer::T1 {
+        len: 1,
+        payload: Some(vec![4u8, 5u8]),
+        extra: None,
+    }).is_err());
+}
+
+round_trip!(
+    test_trailing_all,
+    test_trailing_all_async;
+    gen_trailing,
+    gen_trailing::T1 {
+        version: 3,
+        ext1: Some(300),
+        ext2: Some(4),
+    },
+    [3u8, 0u8, 44u8, 1u8, 4u8]
+);
+
+round_trip!(
+    test_trailing_partial,
+    test_trailing_partial_async;
+    gen_trailing,
+    gen_trailing::T1 {
+        version: 2,
+        ext1: Some(300),
+        ext2: None,
+    },
+    [2u8, 0u8, 44u8, 1u8]
+);
+
+round_trip!(
+    test_trailing_none,
+    test_trailing_none_async;
+    gen_trailing,
+    gen_trailing::T1 {
+        version: 1,
+        ext1: None,
+        ext2: None,
+    },
+    [1u8, 0u8]
+);
+
+#[test]
+fn test_trailing_gap() {
+    let mut bytes = vec![];
+    assert!(gen_trailing::write(&mut bytes, gen_trailing::T1 {
+        version: 3,
+        ext1: None,
+        ext2: Some(4),
+    }).is_err());
+}
+
+round_trip!(
+    test_int_transform,
+    test_int_transform_async;
+    gen_int_transform,
+    gen_int_transform::T1 {
+        options: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        payload: vec![9, 10],
+    },
+    [2u8, 6u8, 1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8]
+);
+
+#[test]
+fn test_int_transform_inexact() {
+    let mut bytes = vec![];
+    assert!(gen_int_transform::write(&mut bytes, gen_int_transform::T1 {
+        options: vec![1, 2, 3],
+        payload: vec![],
+    }).is_err());
+    assert!(gen_int_transform::read(&mut std::io::Cursor::new(&[0u8, 2u8])).is_err());
+}
+
+#[test]
+fn test_int_transform_wide() {
+    let start = gen_int_transform::T1 {
+        options: vec![7u8; 800],
+        payload: vec![],
+    };
+    let mut bytes = vec![];
+    gen_int_transform::write(&mut bytes, start.clone()).unwrap();
+    assert_eq!(bytes[..2], [200u8, 4u8]);
+    let end = gen_int_transform::read(&mut std::io::Cursor::new(&bytes)).unwrap();
+    assert_eq!(end, start);
+
+    // 256 words doesn't fit in the u8 word count
+    assert!(gen_int_transform::write(&mut vec![], gen_int_transform::T1 {
+        options: vec![7u8; 1024],
+        payload: vec![],
+    }).is_err());
+}
+
+round_trip!(
+    test_custom_len,
+    test_custom_len_async;
+    gen_custom_len,
+    gen_custom_len::T1 { body: vec![7, 8, 9] },
+    [0u8, 3u8, 7u8, 8u8, 9u8]
+);
+
+#[test]
+fn test_dynamic_bytes_len_overflow() {
+    let mut bytes = vec![];
+    assert!(gen_dynamic_bytes::write(&mut bytes, gen_dynamic_bytes::T1 { f: vec![0; 300] }).is_err());
+}
+
+round_trip!(
+    test_fan_out,
+    test_fan_out_async;
+    gen_fan_out,
+    gen_fan_out::T1 {
+        count: 2,
+        xs: vec![1, 2],
+        ys: vec![3, 4],
+    },
+    [2u8, 1u8, 2u8, 3u8, 0u8, 4u8, 0u8]
+);
+
+#[test]
+fn test_fan_out_mismatch() {
+    let mut bytes = vec![];
+    assert!(gen_fan_out::write(&mut bytes, gen_fan_out::T1 {
+        count: 2,
+        xs: vec![1, 2],
+        ys: vec![3],
+    }).is_err());
+    assert!(bytes.is_empty());
+    assert!(gen_fan_out::write(&mut bytes, gen_fan_out::T1 {
+        count: 5,
+        xs: vec![1, 2],
+        ys: vec![3, 4],
+    }).is_err());
+    assert!(bytes.is_empty());
+}
+
+round_trip!(
+    test_fan_out_enum,
+    test_fan_out_enum_async;
+    gen_fan_out_enum,
+    gen_fan_out_enum::T1 {
+        shared: 4,
+        kind: gen_fan_out_enum::Kind::B(gen_fan_out_enum::B { value: 4 }),
+    },
+    [4u8, 1u8]
+);
+
+#[test]
+fn test_fan_out_enum_mismatch() {
+    assert!(gen_fan_out_enum::write(&mut vec![], gen_fan_out_enum::T1 {
+        shared: 4,
+        kind: gen_fan_out_enum::Kind::A(gen_fan_out_enum::A { value: 5 }),
+    }).is_err());
+}
+
+round_trip!(
+    test_checksum,
+    test_checksum_async;
+    gen_checksum,
+    gen_checksum::T1 {
+        note: 5,
+        body: b"123456789".to_vec(),
+    }
+);
+
+#[test]
+fn test_checksum_layout() {
+    let mut bytes = vec![];
+    gen_checksum::write(&mut bytes, gen_checksum::T1 {
+        note: 5,
+        body: b"123456789".to_vec(),
+    }).unwrap();
+    let mut crc = inarybay_runtime::checksum::Crc32::default();
+    inarybay_runtime::checksum::Checksum::update(&mut crc, &[9]);
+    inarybay_runtime::checksum::Checksum::update(&mut crc, b"123456789");
+    let crc = inarybay_runtime::checksum::Checksum::finish(&crc);
+    let mut expected = crc.to_be_bytes().to_vec();
+    expected.extend([9u8, 5u8]);
+    expected.extend(b"123456789");
+    expected.push(9 ^ 5);
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn test_checksum_mismatch() {
+    let mut bytes = vec![];
+    gen_checksum::write(&mut bytes, gen_checksum::T1 {
+        note: 5,
+        body: b"123456789".to_vec(),
+    }).unwrap();
+    let mut body_changed = bytes.clone();
+    body_changed[8] = 0;
+    assert!(gen_checksum::read(&mut std::io::Cursor::new(&body_changed)).is_err());
+    let mut note_changed = bytes.clone();
+    note_changed[5] = 6;
+    assert!(gen_checksum::read(&mut std::io::Cursor::new(&note_changed)).is_err());
+}
+
+round_trip!(
+    test_capture,
+    test_capture_async;
+    gen_capture,
+    gen_capture::T1 {
+        head: 0x12,
+        head_raw: Some(vec![0x12, 0x00]),
+        body: vec![7, 8],
+        body_raw: Some(vec![7, 8]),
+    },
+    [0x12u8, 0x00u8, 7u8, 8u8]
+);
+
+#[test]
+fn test_capture_verbatim() {
+    let mut bytes = vec![];
+    gen_capture::write(&mut bytes, gen_capture::T1 {
+        head: 0x12,
+        head_raw: Some(vec![0x12, 0xFF]),
+        body: vec![7, 8],
+        body_raw: Some(vec![9]),
+    }).unwrap();
+    assert_eq!(bytes, vec![0x12, 0xFF, 7, 8]);
+}
+
+#[test]
+fn test_capture_verbatim_modified() {
+    let mut bytes = vec![];
+    gen_capture::write(&mut bytes, gen_capture::T1 {
+        head: 0x34,
+        head_raw: Some(vec![0x12, 0xFF]),
+        body: vec![7, 8],
+        body_raw: None,
+    }).unwrap();
+    assert_eq!(bytes, vec![0x34, 0x00, 7, 8]);
+}
+
+#[tokio::test]
+async fn test_capture_verbatim_modified_async() {
+    let mut bytes = vec![];
+    gen_capture::write_async(&mut bytes, gen_capture::T1 {
+        head: 0x34,
+        head_raw: Some(vec![0x12, 0xFF]),
+        body: vec![7, 8],
+        body_raw: None,
+    }).await.unwrap();
+    assert_eq!(bytes, vec![0x34, 0x00, 7, 8]);
+}
+
+#[test]
+fn test_capture_reserialize() {
+    let mut bytes = vec![];
+    gen_capture::write(&mut bytes, gen_capture::T1 {
+        head: 0x12,
+        head_raw: None,
+        body: vec![7, 8],
+        body_raw: None,
+    }).unwrap();
+    assert_eq!(bytes, vec![0x12, 0x00, 7, 8]);
+    let end = gen_capture::read(&mut std::io::Cursor::new(&bytes)).unwrap();
+    assert_eq!(end.head_raw, Some(vec![0x12, 0x00]));
+    assert_eq!(end.body_raw, Some(vec![7, 8]));
+}
+
+round_trip!(
+    test_decode,
+    test_decode_async;
+    gen_decode,
+    gen_decode::T1 {
+        zlib: gen_decode::T2 {
+            a: 0x12345678,
+            rest: b"hello hello hello hello".to_vec(),
+        },
+        xor: vec![0x00, 0xFF, 0xAA],
+    }
+);
+
+#[test]
+fn test_decode_serial() {
+    let mut bytes = vec![];
+    gen_decode::write(&mut bytes, gen_decode::T1 {
+        zlib: gen_decode::T2 {
+            a: 1,
+            rest: vec![],
+        },
+        xor: vec![0x00, 0xFF, 0xAA],
+    }).unwrap();
+    let zlen = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+    assert_eq!(bytes.len(), 2 + zlen + 3);
+    assert_eq!(bytes[2 + zlen..], [0xAA, 0xAA, 0x00]);
+}
+
+#[test]
+fn test_decode_corrupt() {
+    let bytes = vec![0u8, 2, 0xFF, 0xFF];
+    assert!(gen_decode::read(&mut std::io::Cursor::new(&bytes)).is_err());
+}
+
+round_trip!(
+    test_decode_codecs,
+    test_decode_codecs_async;
+    gen_decode_codecs,
+    gen_decode_codecs::T1 {
+        deflate: b"hello hello hell".to_vec(),
+        gzip: b"hello gzip".to_vec(),
+        lz4: b"hello lz4".to_vec(),
+        zstd: b"hello zstd".to_vec(),
+        xor: b"hello xor".to_vec(),
+    }
+);
+
+#[test]
+fn test_decode_codecs_max_size() {
+    let fits = || gen_decode_codecs::T1 {
+        deflate: vec![],
+        gzip: vec![],
+        lz4: vec![],
+        zstd: vec![],
+        xor: vec![],
+    };
+    let mut cases = vec![];
+    for i in 0 .. 5 {
+        let mut value = fits();
+        let field = match i {
+            0 => &mut value.deflate,
+            1 => &mut value.gzip,
+            2 => &mut value.lz4,
+            3 => &mut value.zstd,
+            _ => &mut value.xor,
+        };
+        *field = vec![7u8; 17];
+        cases.push(value);
+    }
+    for value in cases {
+        let mut bytes = vec![];
+        gen_decode_codecs::write(&mut bytes, value).unwrap();
+        assert!(gen_decode_codecs::read(&mut std::io::Cursor::new(&bytes)).is_err());
+    }
+}
+
+round_trip!(
+    test_framed,
+    test_framed_async;
+    gen_framed,
+    gen_framed::T1 {
+        cobs: vec![0x11, 0x00, 0x22],
+        slip: vec![0xC0, 0x01, 0xDB],
+        hdlc: gen_framed::T2 {
+            a: 0x7E7D,
+            rest: vec![0x01],
+        },
+    },
+    [
+        0x02u8,
+        0x11u8,
+        0x02u8,
+        0x22u8,
+        0x00u8,
+        0xDBu8,
+        0xDCu8,
+        0x01u8,
+        0xDBu8,
+        0xDDu8,
+        0xC0u8,
+        0x7Eu8,
+        0x7Du8,
+        0x5Eu8,
+        0x7Du8,
+        0x5Du8,
+        0x01u8,
+        0x7Eu8,
+    ]
+);
+
+#[test]
+fn test_framed_long_cobs() {
+    let start = gen_framed::T1 {
+        cobs: (0 .. 600).map(|i| (i % 7) as u8).chain((0 .. 300).map(|_| 1u8)).collect(),
+        slip: vec![0x01],
+        hdlc: gen_framed::T2 {
+            a: 0,
+            rest: vec![],
+        },
+    };
+    let mut bytes = vec![];
+    gen_framed::write(&mut bytes, start.clone()).unwrap();
+    assert_eq!(gen_framed::read(&mut std::io::Cursor::new(&bytes)).unwrap(), start);
+}
+
+#[test]
+fn test_framed_empty() {
+    let mut bytes = vec![];
+    assert!(gen_framed::write(&mut bytes, gen_framed::T1 {
+        cobs: vec![],
+        slip: vec![],
+        hdlc: gen_framed::T2 {
+            a: 0,
+            rest: vec![],
+        },
+    }).is_err());
+}
+
+#[test]
+fn test_framed_bad_escape() {
+    let bytes = vec![0x01u8, 0x00, 0xDB, 0x01, 0xC0, 0x7E, 0x00, 0x00, 0x7E];
+    assert!(gen_framed::read(&mut std::io::Cursor::new(&bytes)).is_err());
+}
+
+#[test]
+fn test_framed_unterminated() {
+    let bytes = vec![0x01u8, 0x00, 0x01];
+    assert!(gen_framed::read(&mut std::io::Cursor::new(&bytes)).is_err());
+}
+
+round_trip!(
+    test_delimited_ext,
+    test_delimited_ext_async;
+    gen_delimited_ext,
+    gen_delimited_ext::T1 {
+        a: b"x;y\\z".to_vec(),
+        b: b"line\r".to_vec(),
+        c: b"end".to_vec(),
+    },
+    [
+        b'x',
+        b'\\',
+        b';',
+        b'y',
+        b'\\',
+        b'\\',
+        b'z',
+        b';',
+        b'l',
+        b'i',
+        b'n',
+        b'e',
+        b'\r',
+        b'\r',
+        b'\n',
+        b'e',
+        b'n',
+        b'd',
+        0u8,
+    ]
+);
+
+#[test]
+fn test_delimited_ext_alt_delimiter() {
+    let bytes = b"a;b\nc\0".to_vec();
+    let end = gen_delimited_ext::read(&mut std::io::Cursor::new(&bytes)).unwrap();
+    assert_eq!(end, gen_delimited_ext::T1 {
+        a: b"a".to_vec(),
+        b: b"b".to_vec(),
+        c: b"c".to_vec(),
+    });
+}
+
+#[test]
+fn test_delimited_ext_contains_delimiter() {
+    let mut bytes = vec![];
+    assert!(gen_delimited_ext::write(&mut bytes, gen_delimited_ext::T1 {
+        a: vec![],
+        b: b"a\nb".to_vec(),
+        c: vec![],
+    }).is_err());
+}
+
+round_trip!(
+    test_delimited_lf,
+    test_delimited_lf_async;
+    gen_delimited_lf,
+    gen_delimited_lf::T1 {
+        lf: b"a".to_vec(),
+        lf_esc: b"a\r".to_vec(),
+    },
+    [b'a', b'\n', b'a', b'\\', b'\r', b'\n']
+);
+
+#[test]
+fn test_delimited_lf_ends_with_longer_delimiter() {
+    let mut bytes = vec![];
+    assert!(gen_delimited_lf::write(&mut bytes, gen_delimited_lf::T1 {
+        lf: b"a\r".to_vec(),
+        lf_esc: vec![],
+    }).is_err());
+}
+
+#[test]
+fn test_delimited_ext_strict() {
+    let bytes = b"a;b\nc".to_vec();
+    assert!(gen_delimited_ext::read(&mut std::io::Cursor::new(&bytes)).is_err());
+}
+
+round_trip!(
+    test_tlv,
+    test_tlv_async;
+    gen_tlv,
+    gen_tlv::T1 {
+        opts: vec![
+            gen_tlv::Opt::Name(b"ab".to_vec()),
+            gen_tlv::Opt::Unknown(7, vec![1, 2, 3]),
+            gen_tlv::Opt::Group(0x0102)
+        ],
+        chunks: vec![gen_tlv::Chunk::Data(vec![9]), gen_tlv::Chunk::Unknown(2, vec![])],
+        ad: vec![gen_tlv::Ad::Unknown(0x01, vec![0x06]), gen_tlv::Ad::Name(b"x".to_vec())],
+    },
+    [
+        0u8,
+        19u8,
+        0u8,
+        0u8,
+        0u8,
+        2u8,
+        b'a',
+        b'b',
+        0u8,
+        7u8,
+        0u8,
+        3u8,
+        1u8,
+        2u8,
+        3u8,
+        0u8,
+        10u8,
+        0u8,
+        2u8,
+        1u8,
+        2u8,
+        1u8,
+        1u8,
+        9u8,
+        2u8,
+        0u8,
+        0xFFu8,
+        0u8,
+        2u8,
+        0x01u8,
+        0x06u8,
+        2u8,
+        0x09u8,
+        b'x',
+    ]
+);
+
+#[test]
+fn test_tlv_truncated() {
+    let bytes = vec![0u8, 4, 0, 0, 0, 5, b'a'];
+    assert!(gen_tlv::read(&mut std::io::Cursor::new(&bytes)).is_err());
+}
+
+#[test]
+fn test_tlv_known_trailing() {
+    let bytes = vec![0u8, 6, 0, 10, 0, 2, 1, 2, 0xFF, 0];
+    let end = gen_tlv::read(&mut std::io::Cursor::new(&bytes)).unwrap();
+    assert_eq!(end.opts, vec![gen_tlv::Opt::Group(0x0102)]);
+    let bytes = vec![0u8, 7, 0, 10, 0, 3, 1, 2, 3, 0xFF, 0];
+    let err = gen_tlv::read(&mut std::io::Cursor::new(&bytes)).unwrap_err();
+    assert_eq!(err.node, "opts_val");
+}
+
+round_trip!(
+    test_stride_array,
+    test_stride_array_async;
+    gen_stride_array,
+    gen_stride_array::T1 {
+        stride: 3,
+        skip: vec![0x0102, 0x0304],
+        keep: vec![(5, vec![6, 7])],
+    },
+    [2u8, 3u8, 1u8, 2u8, 0u8, 3u8, 4u8, 0u8, 1u8, 5u8, 6u8, 7u8]
+);
+
+#[test]
+fn test_stride_array_skip_tail() {
+    let bytes = vec![1u8, 4, 1, 2, 9, 9, 0];
+    let end = gen_stride_array::read(&mut std::io::Cursor::new(&bytes)).unwrap();
+    assert_eq!(end, gen_stride_array::T1 {
+        stride: 4,
+        skip: vec![0x0102],
+        keep: vec![],
+    });
+}
+
+#[test]
+fn test_stride_array_err_index() {
+    let bytes = vec![2u8, 2, 1, 2, 3];
+    let err = gen_stride_array::read(&mut std::io::Cursor::new(&bytes)).unwrap_err();
+    assert_eq!(err.node, "skip_val");
+    assert_eq!(err.index, Some(1));
+}
+
+#[test]
+fn test_stride_array_too_large() {
+    let mut bytes = vec![];
+    assert!(gen_stride_array::write(&mut bytes, gen_stride_array::T1 {
+        stride: 1,
+        skip: vec![0x0102],
+        keep: vec![],
+    }).is_err());
+}
+
+round_trip!(
+    test_dynamic_map,
+    test_dynamic_map_async;
+    gen_dynamic_map,
+    gen_dynamic_map::T1 {
+        tree: std::collections::BTreeMap::from([(2, 0x0203), (1, 0x0101)]),
+        hash: std::collections::HashMap::from([(9, 1), (3, 2), (5, 3)]),
+        points: std::collections::BTreeMap::from([(4, gen_dynamic_map::Point { x: 8 })]),
+    },
+    [2u8, 1u8, 1u8, 1u8, 2u8, 2u8, 3u8, 3u8, 3u8, 2u8, 5u8, 3u8, 9u8, 1u8, 1u8, 4u8, 8u8]
+);
+
+#[test]
+fn test_dynamic_map_duplicate() {
+    let bytes = vec![2u8, 1, 0, 1, 1, 0, 2, 0];
+    let err = gen_dynamic_map::read(&mut std::io::Cursor::new(&bytes)).unwrap_err();
+    assert_eq!(err.node, "tree_val");
+    assert_eq!(err.index, Some(1));
+}
+
+#[test]
+fn test_dynamic_map_last_wins() {
+    let bytes = vec![0u8, 2, 1, 4, 1, 5, 0];
+    let end = gen_dynamic_map::read(&mut std::io::Cursor::new(&bytes)).unwrap();
+    assert_eq!(end, gen_dynamic_map::T1 {
+        tree: std::collections::BTreeMap::new(),
+        hash: std::collections::HashMap::from([(1, 5)]),
+        points: std::collections::BTreeMap::new(),
+    });
+}
+
+round_trip!(
+    test_previous,
+    test_previous_async;
+    gen_previous,
+    gen_previous::T1 { times: vec![1005, 1007, 1017] },
+    [3u8, 5u8, 2u8, 10u8]
+);
+
+#[test]
+fn test_previous_empty() {
+    let end = gen_previous::read(&mut std::io::Cursor::new(&[0u8])).unwrap();
+    assert_eq!(end, gen_previous::T1 { times: vec![] });
+}
+
+round_trip!(
+    test_array_external,
+    test_array_external_async;
+    gen_array_external,
+    gen_array_external::T1 {
+        rows: vec![vec![1, 2], vec![3, 4]],
+        width: 2,
+        depth: 1,
+        cols: vec![vec![5]],
+    },
+    [2u8, 2u8, 1u8, 2u8, 3u8, 4u8, 1u8, 1u8, 5u8]
+);
+
+round_trip!(
+    test_array_external_empty,
+    test_array_external_empty_async;
+    gen_array_external,
+    gen_array_external::T1 {
+        rows: vec![],
+        width: 7,
+        depth: 3,
+        cols: vec![],
+    },
+    [7u8, 0u8, 3u8, 0u8]
+);
+
+#[test]
+fn test_array_external_mismatch() {
+    let mut bytes = vec![];
+    assert!(gen_array_external::write(&mut bytes, gen_array_external::T1 {
+        rows: vec![vec![1, 2], vec![3]],
+        width: 2,
+        depth: 1,
+        cols: vec![],
+    }).is_err());
+    assert!(gen_array_external::write(&mut bytes, gen_array_external::T1 {
+        rows: vec![vec![1, 2]],
+        width: 3,
+        depth: 1,
+        cols: vec![],
+    }).is_err());
+    assert!(gen_array_external::write(&mut bytes, gen_array_external::T1 {
+        rows: vec![vec![1]],
+        width: 1,
+        depth: 1,
+        cols: vec![vec![5, 6]],
+    }).is_err());
+}
+
+round_trip!(
+    test_array_index,
+    test_array_index_async;
+    gen_array_index,
+    gen_array_index::T1 {
+        items: vec![gen_array_index::Item {
+            value: 10,
+            extra: Some(7),
+        }, gen_array_index::Item {
+            value: 10,
+            extra: None,
+        }, gen_array_index::Item {
+            value: 10,
+            extra: Some(8),
+        }],
+    },
+    [3u8, 10u8, 7u8, 9u8, 8u8, 8u8]
+);
+
+#[test]
+fn test_array_index_condition_mismatch() {
+    let mut bytes = vec![];
+    assert!(gen_array_index::write(&mut bytes, gen_array_index::T1 { items: vec![gen_array_index::Item {
+        value: 10,
+        extra: None,
+    }] }).is_err());
+}
+
+round_trip!(
+    test_type_def,
+    test_type_def_async;
+    gen_type_def,
+    gen_type_def::T1 {
+        first: gen_type_def::Header {
+            version: 1,
+            name: b"ab".to_vec(),
+        },
+        rest: vec![gen_type_def::Header {
+            version: 2,
+            name: b"c".to_vec(),
+        }],
+    },
+    [1u8, 2u8, b'a', b'b', 1u8, 2u8, 1u8, b'c']
+);
+
+round_trip!(
+    test_type_def_params,
+    test_type_def_params_async;
+    gen_type_def_params,
+    gen_type_def_params::T1 {
+        version: 2,
+        width: 2,
+        first: gen_type_def_params::Entry {
+            data: vec![1, 2],
+            extra: Some(3),
+        },
+        rest: vec![gen_type_def_params::Entry {
+            data: vec![4, 5],
+            extra: Some(6),
+        }],
+    },
+    [2u8, 2u8, 1u8, 2u8, 3u8, 1u8, 4u8, 5u8, 6u8]
+);
+
+#[test]
+fn test_type_def_params_old_version() {
+    let bytes = vec![1u8, 1, 7, 1, 8];
+    let end = gen_type_def_params::read(&mut std::io::Cursor::new(&bytes)).unwrap();
+    assert_eq!(end, gen_type_def_params::T1 {
+        version: 1,
+        width: 1,
+        first: gen_type_def_params::Entry {
+            data: vec![7],
+            extra: None,
+        },
+        rest: vec![gen_type_def_params::Entry {
+            data: vec![8],
+            extra: None,
+        }],
+    });
+}
+
+#[test]
+fn test_type_def_params_width_mismatch() {
+    let mut bytes = vec![];
+    assert!(gen_type_def_params::write(&mut bytes, gen_type_def_params::T1 {
+        version: 1,
+        width: 2,
+        first: gen_type_def_params::Entry {
+            data: vec![7],
+            extra: None,
+        },
+        rest: vec![],
+    }).is_err());
+}
+
+round_trip!(
+    test_type_def_recursive,
+    test_type_def_recursive_async;
+    gen_type_def_recursive,
+    gen_type_def_recursive::Tree {
+        value: 1,
+        children: vec![gen_type_def_recursive::Tree {
+            value: 2,
+            children: vec![],
+            link: None,
+        }],
+        link: Some(Box::new(gen_type_def_recursive::Tree {
+            value: 3,
+            children: vec![],
+            link: None,
+        })),
+    },
+    [1u8, 1u8, 2u8, 0u8, 0u8, 1u8, 3u8, 0u8, 0u8]
+);
+
+#[test]
+fn test_type_def_recursive_max_depth() {
+    let mut bytes = vec![];
+    for _ in 0 .. 4 {
+        bytes.extend([0u8, 0u8, 1u8]);
+    }
+    bytes.extend([0u8, 0u8, 0u8]);
+    assert!(gen_type_def_recursive::read(&mut std::io::Cursor::new(&bytes)).is_err());
+    let bytes = bytes[3..].to_vec();
+    assert!(gen_type_def_recursive::read(&mut std::io::Cursor::new(&bytes)).is_ok());
+}
+
+round_trip!(
+    test_type_fns,
+    test_type_fns_async;
+    gen_type_fns,
+    gen_type_fns::T1 {
+        a: vec![gen_type_fns::Point {
+            x: 1,
+            y: 2,
+        }],
+        b: vec![gen_type_fns::Point {
+            x: 3,
+            y: 4,
+        }, gen_type_fns::Point {
+            x: 5,
+            y: 6,
+        }],
+    },
+    [1u8, 1u8, 2u8, 2u8, 3u8, 4u8, 5u8, 6u8]
+);
+
+round_trip!(
+    test_plugin,
+    test_plugin_async;
+    gen_plugin,
+    gen_plugin::T1 {
+        a: "xyz".to_string(),
+        b: -3,
+    },
+    [b'x', b'y', b'z', 5u8]
+);
+
+#[test]
+fn test_plugin_errors() {
+    assert!(gen_plugin::read(&mut std::io::Cursor::new(&[0xffu8, 0u8, 0u8, 0u8])).is_err());
+    assert!(gen_plugin::write(&mut vec![], gen_plugin::T1 {
+        a: "xy".to_string(),
+        b: 0,
+    }).is_err());
+}
+
+round_trip!(
+    test_custom_write_err,
+    test_custom_write_err_async;
+    gen_custom_write_err,
+    gen_custom_write_err::T1 { name: "abc".to_string() },
+    [b'a', b'b', b'c']
+);
+
+#[test]
+fn test_custom_write_err_fail() {
+    let err = gen_custom_write_err::write(&mut vec![], gen_custom_write_err::T1 { name: "åbc".to_string() }).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let err = err.get_ref().unwrap().downcast_ref::<inarybay_runtime::error::WriteError>().unwrap();
+    assert_eq!(err.node, "name_val");
+}
+
+#[cfg(test)]
+fn encode_decode<
+    T: inarybay_runtime::traits::Decode + inarybay_runtime::traits::Encode + Clone + PartialEq + std::fmt::Debug,
+>(value: T) -> Vec<u8> {
+    let bytes = value.clone().to_vec().unwrap();
+    assert_eq!(T::from_bytes(&bytes).unwrap(), value);
+    return bytes;
+}
+
+#[test]
+fn test_traits() {
+    use inarybay_runtime::traits::Encode;
+
+    let value = gen_type_def::T1 {
+        first: gen_type_def::Header {
+            version: 1,
+            name: b"ab".to_vec(),
+        },
+        rest: vec![],
+    };
+    assert_eq!(encode_decode(value.clone()), vec![1u8, 2u8, b'a', b'b', 0u8]);
+    let mut buf = [0u8; 8];
+    assert_eq!(value.clone().write_to(&mut buf).unwrap(), 5);
+    assert_eq!(buf[..5], [1u8, 2u8, b'a', b'b', 0u8]);
+    let mut buf = [0u8; 4];
+    assert!(value.write_to(&mut buf).is_err());
+}
+
+#[tokio::test]
+async fn test_traits_async() {
+    use inarybay_runtime::traits::{
+        AsyncDecode,
+        AsyncEncode,
+    };
+
+    let value = gen_type_fns::T1 {
+        a: vec![gen_type_fns::Point {
+            x: 1,
+            y: 2,
+        }],
+        b: vec![],
+    };
+    let mut bytes = vec![];
+    value.clone().encode_async(&mut bytes).await.unwrap();
+    assert_eq!(bytes, vec![1u8, 1u8, 2u8, 0u8]);
+    let end = gen_type_fns::T1::decode_async(&mut futures::io::Cursor::new(&bytes)).await.unwrap();
+    assert_eq!(end, value);
+}
+
+round_trip!(
+    test_delegate,
+    test_delegate_async;
+    gen_delegate,
+    gen_delegate::T1 {
+        a: 7,
+        b: crate::varint::VarInt(300),
+        at: crate::varint::ReadOffset(3),
+        c: vec![9u8, 8u8],
+    },
+    [7u8, 0xacu8, 0x02u8, 9u8, 8u8]
+);
+
+#[test]
+fn test_delegate_write_offset() {
+    let mut bytes = vec![];
+    assert!(gen_delegate::write(&mut bytes, gen_delegate::T1 {
+        a: 7,
+        b: crate::varint::VarInt(3),
+        at: crate::varint::ReadOffset(3),
+        c: vec![],
+    }).is_err());
+}
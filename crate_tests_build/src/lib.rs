@@ -9,7 +9,23 @@ use inarybay::{
         Schema,
         GenerateConfig,
     },
-    scope::Endian,
+    node::node_plugin::{
+        Plugin,
+        PluginRead,
+        PluginWrite,
+    },
+    scope::{
+        Endian,
+        IntOp,
+        Checksum,
+        CaptureWrite,
+        Codec,
+        Framing,
+        DelimitedConfig,
+        TlvConfig,
+        TlvEnd,
+        MapType,
+    },
 };
 use quote::quote;
 
@@ -51,6 +67,80 @@ mod example {
     }
 }
 
+/// Fixed-length UTF-8 string, read directly from the stream.
+struct FixedStr(usize);
+
+impl Plugin for FixedStr {
+    fn rust_type(&self) -> proc_macro2::TokenStream {
+        return quote!(std:: string:: String);
+    }
+
+    fn has_serial(&self) -> bool {
+        return true;
+    }
+
+    fn generate_read(&self, ctx: &PluginRead) -> proc_macro2::TokenStream {
+        let len = self.0;
+        let source = ctx.source.as_ref().unwrap();
+        let offset = &ctx.offset;
+        let dest = &ctx.dest;
+        let method;
+        if ctx.gen_ctx.is_async() {
+            method = quote!(inarybay_runtime:: async_:: read);
+        } else {
+            method = quote!(inarybay_runtime:: read);
+        }
+        let read = ctx.gen_ctx.wrap_read(ctx.id, quote!(#method(#source, #len)));
+        let err = ctx.gen_ctx.new_read_err(ctx.id, "Invalid UTF-8", quote!(e.to_string()));
+        return quote!{
+            let bytes = #read;
+            #offset += bytes.len();
+            #dest = match String:: from_utf8(bytes) {
+                Ok(v) => v,
+                Err(e) => return Err(#err),
+            };
+        };
+    }
+
+    fn generate_write(&self, ctx: &PluginWrite) -> proc_macro2::TokenStream {
+        let len = self.0;
+        let source = &ctx.source;
+        let dest = ctx.dest.as_ref().unwrap();
+        let err = ctx.gen_ctx.new_write_err(ctx.id, "Wrong string length", quote!("Wrong string length"));
+        return quote!{
+            if #source.len() != #len {
+                return Err(#err);
+            }
+            #dest = #source.into_bytes();
+        };
+    }
+}
+
+/// Zigzag-encoded signed byte, transformed from an integer node.
+struct Zigzag;
+
+impl Plugin for Zigzag {
+    fn rust_type(&self) -> proc_macro2::TokenStream {
+        return quote!(i8);
+    }
+
+    fn generate_read(&self, ctx: &PluginRead) -> proc_macro2::TokenStream {
+        let source = &ctx.inputs[0];
+        let dest = &ctx.dest;
+        return quote!{
+            #dest = (#source >> 1) as i8 ^ -((#source & 1) as i8);
+        };
+    }
+
+    fn generate_write(&self, ctx: &PluginWrite) -> proc_macro2::TokenStream {
+        let source = &ctx.source;
+        let dest = &ctx.inputs[0];
+        return quote!{
+            #dest = ((#source << 1) ^ (#source >> 7)) as u8;
+        };
+    }
+}
+
 pub fn generate(root: PathBuf) {
     let src = root.join("src");
     let write = |name: &str, s: Schema| {
@@ -289,6 +379,23 @@ pub fn generate(root: PathBuf) {
         write("dynamic_array", schema);
     }
 
+    // Signed lengths
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let len = scope.int("data_len", scope.fixed_range("data_len_bytes", 1), Endian::Little, true);
+        let data = scope.dynamic_bytes("data_val", len);
+        let count = scope.int("items_len", scope.fixed_range("items_len_bytes", 1), Endian::Little, true);
+        let (items, items_scope) = scope.dynamic_array("items_val", count);
+        items_scope.rust_root(items_scope.int("item_int", items_scope.fixed_range("item_bytes", 1), Endian::Little, false));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("data", data);
+        obj.field("items", items);
+        write("signed_len", schema);
+    }
+
     // Enum
     {
         let schema = inarybay::schema::Schema::new();
@@ -393,4 +500,863 @@ pub fn generate(root: PathBuf) {
         obj.field("g", scope.string_utf8("g_str", scope.remaining_bytes("g_val")));
         write("string", schema);
     }
+
+    // Optional
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let flags = scope.fixed_range("flags", 1);
+        let has_stamp =
+            scope.bool("has_stamp", scope.int("has_stamp_int", scope.subrange(&flags, 0, 1), Endian::Little, false));
+        let other = scope.int("other_val", scope.subrange(&flags, 0, 7), Endian::Little, false);
+        let (stamp, stamp_scope) = scope.optional("stamp_val", has_stamp);
+        stamp_scope.rust_root(
+            stamp_scope.int("stamp_int", stamp_scope.fixed_range("range0", 4), Endian::Little, false),
+        );
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("other", other);
+        obj.field("stamp", stamp);
+        write("optional", schema);
+    }
+
+    // Conditional
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let version = scope.int("version_val", scope.fixed_range("version_bytes", 2), Endian::Little, false);
+        let kind = scope.int("kind_val", scope.fixed_range("kind_bytes", 1), Endian::Little, false);
+        let (extra, extra_scope) =
+            scope.conditional(
+                "extra_val",
+                vec![version.clone().into(), kind.clone().into()],
+                |d| {
+                    let version = &d[0];
+                    let kind = &d[1];
+                    return quote!(#version >= 3 && #kind == 7);
+                },
+            );
+        extra_scope.rust_root(
+            extra_scope.int("extra_int", extra_scope.fixed_range("range0", 2), Endian::Little, false),
+        );
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("version", version);
+        obj.field("kind", kind);
+        obj.field("extra", extra);
+        write("conditional", schema);
+    }
+
+    // Optional values using outer values
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let len = scope.int("len_val", scope.fixed_range("len_bytes", 1), Endian::Little, false);
+        let has_payload =
+            scope.bool("has_payload", scope.int("has_payload_int", scope.fixed_range("has_payload_bytes", 1), Endian::Little, false));
+        let (payload, payload_scope) = scope.optional("payload_val", has_payload);
+        payload_scope.rust_root(payload_scope.dynamic_bytes("payload_bytes", len.clone()));
+        let (extra, extra_scope) = scope.conditional("extra_val", vec![len.clone().into()], |d| {
+            let len = &d[0];
+            return quote!(#len > 1);
+        });
+        extra_scope.rust_root(extra_scope.dynamic_bytes("extra_bytes", len.clone()));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("len", len);
+        obj.field("payload", payload);
+        obj.field("extra", extra);
+        write("optional_outer", schema);
+    }
+
+    // Trailing
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let version = scope.int("version_val", scope.fixed_range("version_bytes", 2), Endian::Little, false);
+        let (ext1, ext1_scope) = scope.trailing("ext1_val");
+        ext1_scope.rust_root(
+            ext1_scope.int("ext1_int", ext1_scope.fixed_range("range0", 2), Endian::Little, false),
+        );
+        let (ext2, ext2_scope) = scope.trailing("ext2_val");
+        ext2_scope.rust_root(
+            ext2_scope.int("ext2_int", ext2_scope.fixed_range("range0", 1), Endian::Little, false),
+        );
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("version", version);
+        obj.field("ext1", ext1);
+        obj.field("ext2", ext2);
+        write("trailing", schema);
+    }
+
+    // Int transform
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let words = scope.int("words_int", scope.fixed_range("words_bytes", 1), Endian::Little, false);
+        let len = scope.int("len_int", scope.fixed_range("len_bytes", 1), Endian::Little, false);
+        let options =
+            scope.dynamic_bytes("options_val", scope.int_transform("options_len", words, IntOp::Mul(4)));
+        let payload =
+            scope.dynamic_bytes("payload_val", scope.int_transform("payload_len", len, IntOp::Sub(4)));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("options", options);
+        obj.field("payload", payload);
+        write("int_transform", schema);
+    }
+
+    // Custom length
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let len_bytes = scope.bytes("len_bytes", scope.fixed_range("len_range", 2));
+        let len = scope.custom("len_val", quote!(u16), |s, d| {
+            let s = &s[0];
+            return quote!{
+                #d = u16:: from_be_bytes(#s);
+            };
+        }, |s, d| {
+            let d = &d[0];
+            return quote!{
+                #d = #s.to_be_bytes();
+            };
+        }, vec![len_bytes.into()]);
+        let body = scope.dynamic_bytes("body_val", len);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("body", body);
+        write("custom_len", schema);
+    }
+
+    // Fan out
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Little, false);
+        let (xs, xs_scope) = scope.dynamic_array("xs_val", count.clone());
+        xs_scope.rust_root(xs_scope.int("xs_int", xs_scope.fixed_range("range0", 1), Endian::Little, false));
+        let (ys, ys_scope) = scope.dynamic_array("ys_val", count.clone());
+        ys_scope.rust_root(ys_scope.int("ys_int", ys_scope.fixed_range("range0", 2), Endian::Little, false));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("count", count);
+        obj.field("xs", xs);
+        obj.field("ys", ys);
+        write("fan_out", schema);
+    }
+
+    // Fan out to the same scope and an enum
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let shared = scope.int("shared_val", scope.fixed_range("shared_bytes", 1), Endian::Little, false);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("shared", shared.clone());
+        let tag = scope.int("kind_tag", scope.fixed_range("kind_bytes", 1), Endian::Little, false);
+        let enum_ = scope.enum_("kind_val", tag, "Kind");
+        enum_.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        for (id, name, tag) in [("a", "A", quote!(0u8)), ("b", "B", quote!(1u8))] {
+            let variant = enum_.variant(format!("var_{}", id), name, tag);
+            let var_obj = variant.object(format!("{}_obj", id), name);
+            var_obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+            var_obj.field("value", shared.clone());
+            variant.rust_root(var_obj);
+        }
+        obj.field("kind", enum_);
+        write("fan_out_enum", schema);
+    }
+
+    // Checksum
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let crc = scope.checksum("crc", Checksum::Crc32, Endian::Big, vec![]);
+        let len_range = scope.fixed_range("len_range", 1);
+        let len = scope.int("len", len_range.clone(), Endian::Big, false);
+        let note_range = scope.fixed_range("note_range", 1);
+        let note = scope.int("note_int", note_range.clone(), Endian::Big, false);
+        let body = scope.dynamic_bytes("body_bytes", len);
+        crc.cover(len_range.node());
+        crc.cover(body.clone());
+
+        // Covers segments before it
+        scope.checksum("xor", Checksum::Xor, Endian::Big, vec![len_range.node(), note_range.node()]);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("note", note);
+        obj.field("body", body);
+        write("checksum", schema);
+    }
+
+    // Capture
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let (head, head_raw, head_scope) = scope.capture("head_val", CaptureWrite::Verbatim);
+        let head_range = head_scope.fixed_range("range0", 2);
+
+        // The second byte is ignored when reading, so different raw bytes can read as
+        // the same value
+        head_scope.rust_root(head_scope.int("head_int", head_scope.subrange(&head_range, 1, 0), Endian::Big, false));
+        let (body, body_raw, body_scope) = scope.capture("body_val", CaptureWrite::Reserialize);
+        body_scope.rust_root(body_scope.remaining_bytes("body_bytes"));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("head", head);
+        obj.field("head_raw", head_raw);
+        obj.field("body", body);
+        obj.field("body_raw", body_raw);
+        write("capture", schema);
+    }
+
+    // Decode
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let zlen = scope.int("zlen", scope.fixed_range("zlen_bytes", 2), Endian::Big, false);
+        let zbytes = scope.dynamic_bytes("zbytes", zlen);
+        let (zlib, zlib_scope) = scope.decode("zlib_val", zbytes, Codec::Zlib, 1024);
+        {
+            let inner = zlib_scope.object("inner", "T2");
+            zlib_scope.rust_root(inner.clone());
+            inner.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+            inner.field("a", zlib_scope.int("a_int", zlib_scope.fixed_range("a_bytes", 4), Endian::Little, false));
+            inner.field("rest", zlib_scope.remaining_bytes("rest_bytes"));
+        }
+        let xbytes = scope.remaining_bytes("xbytes");
+        let (xor, xor_scope) = scope.decode("xor_val", xbytes, Codec::Xor(vec![0xAA, 0x55]), 1024);
+        xor_scope.rust_root(xor_scope.remaining_bytes("xor_bytes"));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("zlib", zlib);
+        obj.field("xor", xor);
+        write("decode", schema);
+    }
+
+    // Decode, other codecs
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        for (name, codec) in [
+            ("deflate", Codec::Deflate),
+            ("gzip", Codec::Gzip),
+            ("lz4", Codec::Lz4),
+            ("zstd", Codec::Zstd),
+            ("xor", Codec::Xor(vec![0x12, 0x34, 0x56])),
+        ] {
+            let len = scope.int(format!("{}_len", name), scope.fixed_range(format!("{}_len_bytes", name), 2), Endian::Big, false);
+            let bytes = scope.dynamic_bytes(format!("{}_bytes", name), len);
+            let (value, value_scope) = scope.decode(format!("{}_val", name), bytes, codec, 16);
+            value_scope.rust_root(value_scope.remaining_bytes(format!("{}_inner", name)));
+            obj.field(name, value);
+        }
+        write("decode_codecs", schema);
+    }
+
+    // Framed
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let (cobs, cobs_scope) = scope.framed("cobs_val", Framing::Cobs);
+        cobs_scope.rust_root(cobs_scope.remaining_bytes("cobs_bytes"));
+        let (slip, slip_scope) = scope.framed("slip_val", Framing::Slip);
+        slip_scope.rust_root(slip_scope.remaining_bytes("slip_bytes"));
+        let (hdlc, hdlc_scope) = scope.framed("hdlc_val", Framing::Hdlc);
+        {
+            let inner = hdlc_scope.object("inner", "T2");
+            hdlc_scope.rust_root(inner.clone());
+            inner.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+            inner.field("a", hdlc_scope.int("a_int", hdlc_scope.fixed_range("a_bytes", 2), Endian::Big, false));
+            inner.field("rest", hdlc_scope.remaining_bytes("rest_bytes"));
+        }
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("cobs", cobs);
+        obj.field("slip", slip);
+        obj.field("hdlc", hdlc);
+        write("framed", schema);
+    }
+
+    // Delimited bytes, extended
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("a", scope.delimited_bytes_ext("a_val", DelimitedConfig {
+            delimiters: vec![b";".to_vec()],
+            escape: Some(b'\\'),
+            ..Default::default()
+        }));
+        obj.field("b", scope.delimited_bytes_ext("b_val", DelimitedConfig {
+            delimiters: vec![b"\r\n".to_vec(), b"\n".to_vec()],
+            error_on_delimiter: true,
+            ..Default::default()
+        }));
+        obj.field("c", scope.delimited_bytes_ext("c_val", DelimitedConfig {
+            delimiters: vec![b"\0".to_vec()],
+            strict: true,
+            ..Default::default()
+        }));
+        write("delimited_ext", schema);
+    }
+
+    // Delimiters where a longer one ends with the written one
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("lf", scope.delimited_bytes_ext("lf_val", DelimitedConfig {
+            delimiters: vec![b"\n".to_vec(), b"\r\n".to_vec()],
+            error_on_delimiter: true,
+            ..Default::default()
+        }));
+        obj.field("lf_esc", scope.delimited_bytes_ext("lf_esc_val", DelimitedConfig {
+            delimiters: vec![b"\n".to_vec(), b"\r\n".to_vec()],
+            escape: Some(b'\\'),
+            ..Default::default()
+        }));
+        write("delimited_lf", schema);
+    }
+
+    // TLV
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let opts_len = scope.int("opts_len", scope.fixed_range("opts_len_bytes", 2), Endian::Big, false);
+        let opts = scope.tlv("opts_val", "Opt", TlvConfig {
+            type_bytes: 2,
+            len_bytes: 2,
+            endian: Endian::Big,
+            length_first: false,
+            len_includes_type: false,
+            end: TlvEnd::Len(opts_len.into()),
+        });
+        opts.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        {
+            let name_scope = opts.known("opts_name", "Name", quote!(0));
+            name_scope.rust_root(name_scope.remaining_bytes("name_bytes"));
+            let group_scope = opts.known("opts_group", "Group", quote!(10));
+            group_scope.rust_root(
+                group_scope.int("group_int", group_scope.fixed_range("group_bytes", 2), Endian::Big, false),
+            );
+        }
+        let chunks = scope.tlv("chunks_val", "Chunk", TlvConfig {
+            type_bytes: 1,
+            len_bytes: 1,
+            endian: Endian::Big,
+            length_first: false,
+            len_includes_type: false,
+            end: TlvEnd::Terminator(quote!(0xFF)),
+        });
+        chunks.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        {
+            let data_scope = chunks.known("chunks_data", "Data", quote!(1));
+            data_scope.rust_root(data_scope.remaining_bytes("data_bytes"));
+        }
+        let ad = scope.tlv("ad_val", "Ad", TlvConfig {
+            type_bytes: 1,
+            len_bytes: 1,
+            endian: Endian::Little,
+            length_first: true,
+            len_includes_type: true,
+            end: TlvEnd::Eof,
+        });
+        ad.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        {
+            let name_scope = ad.known("ad_name", "Name", quote!(0x09));
+            name_scope.rust_root(name_scope.remaining_bytes("name_bytes"));
+        }
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("opts", opts);
+        obj.field("chunks", chunks);
+        obj.field("ad", ad);
+        write("tlv", schema);
+    }
+
+    // Stride array
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let stride = scope.int("stride_val", scope.fixed_range("stride_bytes", 1), Endian::Big, false);
+        let (skip, skip_scope) = scope.stride_array("skip_val", count, stride.clone(), false);
+        skip_scope.rust_root(skip_scope.int("elem_int", skip_scope.fixed_range("elem_bytes", 2), Endian::Big, false));
+        let count2 = scope.int("count2_val", scope.fixed_range("count2_bytes", 1), Endian::Big, false);
+        let (keep, keep_scope) = scope.stride_array("keep_val", count2, stride.clone(), true);
+        keep_scope.rust_root(keep_scope.int("elem_int", keep_scope.fixed_range("elem_bytes", 1), Endian::Big, false));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("stride", stride);
+        obj.field("skip", skip);
+        obj.field("keep", keep);
+        write("stride_array", schema);
+    }
+
+    // Dynamic map
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let (tree, tree_scope) = scope.dynamic_map("tree_val", count, MapType::BTree, true);
+        tree.key(tree_scope.int("key_int", tree_scope.fixed_range("key_bytes", 1), Endian::Big, false));
+        tree_scope.rust_root(tree_scope.int("value_int", tree_scope.fixed_range("value_bytes", 2), Endian::Big, false));
+        let count2 = scope.int("count2_val", scope.fixed_range("count2_bytes", 1), Endian::Big, false);
+        let (hash, hash_scope) = scope.dynamic_map("hash_val", count2, MapType::Hash, false);
+        hash.key(hash_scope.int("key_int", hash_scope.fixed_range("key_bytes", 1), Endian::Big, false));
+        hash_scope.rust_root(hash_scope.int("value_int", hash_scope.fixed_range("value_bytes", 1), Endian::Big, false));
+        let count3 = scope.int("count3_val", scope.fixed_range("count3_bytes", 1), Endian::Big, false);
+        let (points, points_scope) = scope.dynamic_map("points_val", count3, MapType::BTree, true);
+        points.key(points_scope.int("key_int", points_scope.fixed_range("key_bytes", 1), Endian::Big, false));
+        let point = points_scope.object("point_obj", "Point");
+        point.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        point.field("x", points_scope.int("x_int", points_scope.fixed_range("x_bytes", 1), Endian::Big, false));
+        points_scope.rust_root(point);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("tree", tree);
+        obj.field("hash", hash);
+        obj.field("points", points);
+        write("dynamic_map", schema);
+    }
+
+    // Previous element
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let (times, times_scope) = scope.dynamic_array("times_val", count);
+        let prev = times.previous("prev_time", quote!(1000u16));
+        let delta = times_scope.int("delta_int", times_scope.fixed_range("delta_bytes", 1), Endian::Big, false);
+        let time = times_scope.custom("time_val", quote!(u16), |s, d| {
+            let delta = &s[0];
+            let prev = &s[1];
+            quote!{
+                #d = #prev + #delta as u16;
+            }
+        }, |s, d| {
+            let delta = &d[0];
+            let prev = &d[1];
+            quote!{
+                #delta = (#s - #prev) as u8;
+            }
+        }, vec![delta.into(), prev.clone().into()]);
+        prev.set_value(time.clone());
+        times_scope.rust_root(time);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("times", times);
+        write("previous", schema);
+    }
+
+    // Array element using outer values
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+
+        // Used by elements before being used outside the array
+        let width = scope.int("width_val", scope.fixed_range("width_bytes", 1), Endian::Big, false);
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let (rows, rows_scope) = scope.dynamic_array("rows_val", count);
+        rows_scope.rust_root(rows_scope.dynamic_bytes("row_bytes", width.clone()));
+        obj.field("rows", rows);
+        obj.field("width", width);
+
+        // Used outside the array first
+        let depth = scope.int("depth_val", scope.fixed_range("depth_bytes", 1), Endian::Big, false);
+        obj.field("depth", depth.clone());
+        let count2 = scope.int("count2_val", scope.fixed_range("count2_bytes", 1), Endian::Big, false);
+        let (cols, cols_scope) = scope.dynamic_array("cols_val", count2);
+        cols_scope.rust_root(cols_scope.dynamic_bytes("col_bytes", depth));
+        obj.field("cols", cols);
+        write("array_external", schema);
+    }
+
+    // Array index
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let (items, items_scope) = scope.dynamic_array("items_val", count);
+        let index = items_scope.array_index("item_index");
+        let base = items_scope.int("base_int", items_scope.fixed_range("base_bytes", 1), Endian::Big, false);
+        let value = items_scope.custom("value_val", quote!(u16), |s, d| {
+            let base = &s[0];
+            let index = &s[1];
+            quote!{
+                #d = #base as u16 + #index as u16;
+            }
+        }, |s, d| {
+            let base = &d[0];
+            let index = &d[1];
+            quote!{
+                #base = (#s - #index as u16) as u8;
+            }
+        }, vec![base.into(), index.clone().into()]);
+        let (extra, extra_scope) = items_scope.conditional("extra_val", vec![index.into()], |deps| {
+            let index = &deps[0];
+            quote!(#index % 2 == 0)
+        });
+        extra_scope.rust_root(extra_scope.int("extra_int", extra_scope.fixed_range("extra_bytes", 1), Endian::Big, false));
+        let item = items_scope.object("item", "Item");
+        items_scope.rust_root(item.clone());
+        item.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        item.field("value", value);
+        item.field("extra", extra);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("items", items);
+        write("array_index", schema);
+    }
+
+    // Type definition
+    {
+        let schema = inarybay::schema::Schema::new();
+        let header = schema.type_def("header");
+        {
+            let scope = header.scope();
+            let version = scope.int("version_val", scope.fixed_range("version_bytes", 1), Endian::Big, false);
+            let name_len = scope.int("name_len", scope.fixed_range("name_len_bytes", 1), Endian::Big, false);
+            let name = scope.dynamic_bytes("name_val", name_len);
+            let obj = scope.object("obj", "Header");
+            scope.rust_root(obj.clone());
+            obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+            obj.field("version", version);
+            obj.field("name", name);
+        }
+        let scope = schema.scope("root", config.clone());
+        let first = scope.instance("first_val", &header);
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let (rest, rest_scope) = scope.dynamic_array("rest_val", count);
+        rest_scope.rust_root(rest_scope.instance("rest_header", &header));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("first", first);
+        obj.field("rest", rest);
+        write("type_def", schema);
+    }
+
+    // Type definition parameters
+    {
+        let schema = inarybay::schema::Schema::new();
+        let entry = schema.type_def("entry");
+        let entry_version = entry.param("entry_version", quote!(u8));
+        let entry_width = entry.param("entry_width", quote!(u8));
+        {
+            let scope = entry.scope();
+            let data = scope.dynamic_bytes("data_val", entry_width.clone());
+            let (extra, extra_scope) = scope.conditional("extra_val", vec![entry_version.clone().into()], |deps| {
+                let version = &deps[0];
+                quote!(#version >= 2)
+            });
+            extra_scope.rust_root(extra_scope.int("extra_int", extra_scope.fixed_range("extra_bytes", 1), Endian::Big, false));
+            let obj = scope.object("obj", "Entry");
+            scope.rust_root(obj.clone());
+            obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+            obj.field("data", data);
+            obj.field("extra", extra);
+        }
+        let scope = schema.scope("root", config.clone());
+        let version = scope.int("version_val", scope.fixed_range("version_bytes", 1), Endian::Big, false);
+        let width = scope.int("width_val", scope.fixed_range("width_bytes", 1), Endian::Big, false);
+        let first = scope.instance("first_val", &entry);
+        first.bind(&entry_version, version.clone());
+        first.bind(&entry_width, width.clone());
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let (rest, rest_scope) = scope.dynamic_array("rest_val", count);
+        let rest_entry = rest_scope.instance("rest_entry", &entry);
+        rest_entry.bind(&entry_version, version.clone());
+        rest_entry.bind(&entry_width, width.clone());
+        rest_scope.rust_root(rest_entry);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("version", version);
+        obj.field("width", width);
+        obj.field("first", first);
+        obj.field("rest", rest);
+        write("type_def_params", schema);
+    }
+
+    // Recursive type definition
+    {
+        let schema = inarybay::schema::Schema::new();
+        let tree = schema.type_def("tree");
+        tree.set_recursive(4);
+        {
+            let scope = tree.scope();
+            let value = scope.int("value_val", scope.fixed_range("value_bytes", 1), Endian::Big, false);
+            let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+            let (children, children_scope) = scope.dynamic_array("children_val", count);
+            children_scope.rust_root(children_scope.instance("child", &tree));
+            let has_link =
+                scope.bool("has_link", scope.int("has_link_int", scope.fixed_range("has_link_bytes", 1), Endian::Big, false));
+            let (link, link_scope) = scope.optional("link_val", has_link);
+            let link_tree = link_scope.instance("link_tree", &tree);
+            link_tree.boxed();
+            link_scope.rust_root(link_tree);
+            let obj = scope.object("obj", "Tree");
+            scope.rust_root(obj.clone());
+            obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+            obj.field("value", value);
+            obj.field("children", children);
+            obj.field("link", link);
+        }
+        let scope = schema.scope("root", config.clone());
+        scope.rust_root(scope.instance("root_tree", &tree));
+        write("type_def_recursive", schema);
+    }
+
+    // Nested types in their own functions
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let point = |scope: &inarybay::scope::Scope| {
+            let x = scope.int("x_val", scope.fixed_range("x_bytes", 1), Endian::Big, false);
+            let y = scope.int("y_val", scope.fixed_range("y_bytes", 1), Endian::Big, false);
+            let obj = scope.object("point_obj", "Point");
+            scope.rust_root(obj.clone());
+            obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+            obj.field("x", x);
+            obj.field("y", y);
+        };
+        let a_count = scope.int("a_count", scope.fixed_range("a_count_bytes", 1), Endian::Big, false);
+        let (a, a_scope) = scope.dynamic_array("a_val", a_count);
+        point(&a_scope);
+        let b_count = scope.int("b_count", scope.fixed_range("b_count_bytes", 1), Endian::Big, false);
+        let (b, b_scope) = scope.dynamic_array("b_val", b_count);
+        point(&b_scope);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("a", a);
+        obj.field("b", b);
+        write("type_fns", schema);
+    }
+
+    // Hand-written type
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let a = scope.int("a_val", scope.fixed_range("a_bytes", 1), Endian::Big, false);
+        let b = scope.delegate("b_val", quote!(crate:: varint:: VarInt));
+        let at = scope.delegate("at_val", quote!(crate:: varint:: ReadOffset));
+        let c = scope.remaining_bytes("c_val");
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("a", a);
+        obj.field("b", b);
+        obj.field("at", at);
+        obj.field("c", c);
+        write("delegate", schema);
+    }
+
+    // Plugin nodes
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let a = scope.plugin("a_val", FixedStr(3), vec![]);
+        let b_int = scope.int("b_int", scope.fixed_range("b_bytes", 1), Endian::Big, false);
+        let b = scope.plugin("b_val", Zigzag, vec![b_int.into()]);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("a", a);
+        obj.field("b", b);
+        write("plugin", schema);
+    }
+
+    // Custom node with fallible writing
+    {
+        let schema = inarybay::schema::Schema::new();
+        let scope = schema.scope("root", config.clone());
+        let name_bytes = scope.remaining_bytes("name_bytes");
+        let name = scope.custom("name_val", quote!(String), |s, d| {
+            let s = &s[0];
+            return quote!{
+                #d = String:: from_utf8(#s).errorize("name_val") ?;
+            };
+        }, |s, d| {
+            let d = &d[0];
+            return quote!{
+                #d = if #s.is_ascii() {
+                    Ok(#s.into_bytes())
+                } else {
+                    Err("Name isn't ASCII")
+                }.errorize_write("name_val") ?;
+            };
+        }, vec![name_bytes.into()]);
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.add_type_attrs(quote!(#[derive(Clone, Debug, PartialEq)]));
+        obj.field("name", name);
+        write("custom_write_err", schema);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use inarybay::{
+        schema::{
+            Schema,
+            GenerateConfig,
+        },
+        scope::{
+            Endian,
+            IntOp,
+            Checksum,
+        },
+    };
+
+    fn config() -> GenerateConfig {
+        return GenerateConfig {
+            read: true,
+            write: true,
+            sync_: true,
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    #[should_panic(expected = "can't follow trailing value")]
+    fn test_trailing_not_last() {
+        let schema = Schema::new();
+        let scope = schema.scope("root", config());
+        scope.trailing("ext_val");
+        scope.remaining_bytes("rest_val");
+    }
+
+    #[test]
+    #[should_panic(expected = "must be in a top level scope")]
+    fn test_trailing_nested() {
+        let schema = Schema::new();
+        let scope = schema.scope("root", config());
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let (_, element) = scope.dynamic_array("items_val", count);
+        element.trailing("ext_val");
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit in the input type u8")]
+    fn test_int_transform_operand_range() {
+        let schema = Schema::new();
+        let scope = schema.scope("root", config());
+        let len = scope.int("len_val", scope.fixed_range("len_bytes", 1), Endian::Big, false);
+        scope.int_transform("len_scaled", len, IntOp::Add(300));
+    }
+
+    #[test]
+    #[should_panic(expected = "width_val is only used in array elements")]
+    fn test_array_external_element_only() {
+        let schema = Schema::new();
+        let scope = schema.scope("root", config());
+        let width = scope.int("width_val", scope.fixed_range("width_bytes", 1), Endian::Big, false);
+        let count = scope.int("count_val", scope.fixed_range("count_bytes", 1), Endian::Big, false);
+        let (rows, rows_scope) = scope.dynamic_array("rows_val", count);
+        rows_scope.rust_root(rows_scope.dynamic_bytes("row_bytes", width));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.field("rows", rows);
+        schema.generate();
+    }
+
+    #[test]
+    #[should_panic(expected = "len_val is only used in optional payload_val")]
+    fn test_optional_outer_only() {
+        let schema = Schema::new();
+        let scope = schema.scope("root", config());
+        let len = scope.int("len_val", scope.fixed_range("len_bytes", 1), Endian::Big, false);
+        let has_payload =
+            scope.bool("has_payload", scope.int("has_payload_int", scope.fixed_range("has_payload_bytes", 1), Endian::Big, false));
+        let (payload, payload_scope) = scope.optional("payload_val", has_payload);
+        payload_scope.rust_root(payload_scope.dynamic_bytes("payload_bytes", len));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.field("payload", payload);
+        schema.generate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Delegate at_val can't be covered by checksum crc which precedes it")]
+    fn test_delegate_after_covering_checksum() {
+        let schema = Schema::new();
+        let scope = schema.scope("root", config());
+        let crc = scope.checksum("crc", Checksum::Xor, Endian::Big, vec![]);
+        let at = scope.delegate("at_val", quote::quote!(crate:: varint:: ReadOffset));
+        crc.cover(at.clone());
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.field("at", at);
+        schema.generate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Conditional extra_val depends on kind_val which has no other rust-side consumer")]
+    fn test_conditional_dep_unconsumed() {
+        let schema = Schema::new();
+        let scope = schema.scope("root", config());
+        let kind = scope.int("kind_val", scope.fixed_range("kind_bytes", 1), Endian::Big, false);
+        let (extra, extra_scope) = scope.conditional("extra_val", vec![kind.into()], |d| {
+            let kind = &d[0];
+            return quote::quote!(#kind == 7);
+        });
+        extra_scope.rust_root(extra_scope.int("extra_int", extra_scope.fixed_range("extra_bytes", 1), Endian::Big, false));
+        let obj = scope.object("obj", "T1");
+        scope.rust_root(obj.clone());
+        obj.field("extra", extra);
+        schema.generate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Instance link_tree of recursive definition tree isn't in an array or other container")]
+    fn test_recursive_unboxed() {
+        let schema = Schema::new();
+        let tree = schema.type_def("tree");
+        tree.set_recursive(4);
+        {
+            let scope = tree.scope();
+            let has_link =
+                scope.bool("has_link", scope.int("has_link_int", scope.fixed_range("has_link_bytes", 1), Endian::Big, false));
+            let (link, link_scope) = scope.optional("link_val", has_link);
+            link_scope.rust_root(link_scope.instance("link_tree", &tree));
+            let obj = scope.object("obj", "Tree");
+            scope.rust_root(obj.clone());
+            obj.field("link", link);
+        }
+        let scope = schema.scope("root", config());
+        scope.rust_root(scope.instance("root_tree", &tree));
+        schema.generate();
+    }
 }
@@ -42,12 +42,160 @@ pub fn read_delimited(source: &mut dyn std::io::BufRead, delimiter: &[u8]) -> st
     }
 }
 
+/// Read a frame terminated by `delimiter`, skipping any leading delimiters.  The
+/// returned data includes the delimiters, see `framing::trim_frame`.
+#[inline]
+pub fn read_frame(source: &mut dyn std::io::BufRead, delimiter: u8) -> std::io::Result<Vec<u8>> {
+    let mut out = vec![];
+    loop {
+        let start = out.len();
+        source.read_until(delimiter, &mut out)?;
+        if out.last() != Some(&delimiter) {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "End of data before frame delimiter"));
+        }
+        if out.len() - start > 1 {
+            return Ok(out);
+        }
+    }
+}
+
+/// Read bytes until one of `delimiters`, returning the data without the
+/// delimiter.  If multiple delimiters match the longest is used.  If `escape` is
+/// set, the byte following it is always treated as data.  If `strict` is set,
+/// reaching the end of data before a delimiter is an error, otherwise everything
+/// read is returned.
+pub fn read_delimited_ext(
+    source: &mut dyn std::io::BufRead,
+    delimiters: &[&[u8]],
+    escape: Option<u8>,
+    strict: bool,
+) -> std::io::Result<Vec<u8>> {
+    let mut out = vec![];
+    let mut state = delimited::State::default();
+    loop {
+        let mut b = [0u8];
+        if source.read(&mut b)? == 0 {
+            return delimited::eof(out, &state, strict);
+        }
+        if state.push(&mut out, b[0], delimiters, escape) {
+            return Ok(out);
+        }
+    }
+}
+
+#[inline]
+pub fn at_eof(source: &mut dyn std::io::BufRead) -> std::io::Result<bool> {
+    return Ok(source.fill_buf()?.is_empty());
+}
+
+/// Receives data as it's read, see `ObserveRead`.
+pub trait Observe {
+    fn observe(&mut self, data: &[u8]);
+}
+
+/// Records all observed data.
+impl Observe for Vec<u8> {
+    fn observe(&mut self, data: &[u8]) {
+        self.extend_from_slice(data);
+    }
+}
+
+/// Counts observed bytes.
+impl Observe for usize {
+    fn observe(&mut self, data: &[u8]) {
+        *self += data.len();
+    }
+}
+
+/// Wraps a reader, passing all bytes read through it to an observer.
+pub struct ObserveRead<R, O: Observe> {
+    pub inner: R,
+    pub observer: O,
+}
+
+impl<R, O: Observe> ObserveRead<R, O> {
+    pub fn new(inner: R, observer: O) -> Self {
+        return Self {
+            inner: inner,
+            observer: observer,
+        };
+    }
+}
+
+impl<R: std::io::Read, O: Observe> std::io::Read for ObserveRead<R, O> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.observer.observe(&buf[..count]);
+        return Ok(count);
+    }
+}
+
+impl<R: std::io::BufRead, O: Observe> std::io::BufRead for ObserveRead<R, O> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        return self.inner.fill_buf();
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Data is already buffered so this won't fail
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.observer.observe(&buf[..amt]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(feature = "async")]
+impl<
+    R: futures::io::AsyncRead + Unpin,
+    O: Observe + Unpin,
+> futures::io::AsyncRead for ObserveRead<R, O> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let res = std::pin::Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let std::task::Poll::Ready(Ok(count)) = &res {
+            self.observer.observe(&buf[..*count]);
+        }
+        return res;
+    }
+}
+
+#[cfg(feature = "async")]
+impl<
+    R: futures::io::AsyncBufRead + Unpin,
+    O: Observe + Unpin,
+> futures::io::AsyncBufRead for ObserveRead<R, O> {
+    fn poll_fill_buf(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<&[u8]>> {
+        return std::pin::Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx);
+    }
+
+    fn consume(mut self: std::pin::Pin<&mut Self>, amt: usize) {
+        // Data is already buffered so this will be ready immediately
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let this = &mut *self;
+        if let std::task::Poll::Ready(Ok(buf)) = std::pin::Pin::new(&mut this.inner).poll_fill_buf(&mut cx) {
+            this.observer.observe(&buf[..amt]);
+        }
+        std::pin::Pin::new(&mut this.inner).consume(amt);
+    }
+}
+
 #[cfg(feature = "async")]
 pub mod async_ {
     pub use futures::io::{
+        AsyncRead,
+        AsyncBufRead,
+        AsyncWrite,
         AsyncReadExt,
         AsyncBufReadExt,
         AsyncWriteExt,
+        Cursor,
     };
 
     #[inline]
@@ -95,6 +243,611 @@ pub mod async_ {
             out.extend(&delim_buffer);
         }
     }
+
+    #[inline]
+    pub async fn read_frame<
+        T: futures::io::AsyncBufReadExt + Unpin,
+    >(source: &mut T, delimiter: u8) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        loop {
+            let start = out.len();
+            source.read_until(delimiter, &mut out).await?;
+            if out.last() != Some(&delimiter) {
+                return Err(
+                    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "End of data before frame delimiter"),
+                );
+            }
+            if out.len() - start > 1 {
+                return Ok(out);
+            }
+        }
+    }
+
+    pub async fn read_delimited_ext<
+        T: futures::io::AsyncBufReadExt + Unpin,
+    >(source: &mut T, delimiters: &[&[u8]], escape: Option<u8>, strict: bool) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        let mut state = crate::delimited::State::default();
+        loop {
+            let mut b = [0u8];
+            if source.read(&mut b).await? == 0 {
+                return crate::delimited::eof(out, &state, strict);
+            }
+            if state.push(&mut out, b[0], delimiters, escape) {
+                return Ok(out);
+            }
+        }
+    }
+
+    #[inline]
+    pub async fn at_eof<T: futures::io::AsyncBufReadExt + Unpin>(source: &mut T) -> std::io::Result<bool> {
+        return Ok(source.fill_buf().await?.is_empty());
+    }
+}
+
+pub mod delimited {
+    /// Delimiter matching state for `read_delimited_ext`.
+    #[derive(Default)]
+    pub(crate) struct State {
+        escaping: bool,
+        /// Delimiters can't overlap data before this point (escaped bytes)
+        literal_len: usize,
+    }
+
+    impl State {
+        /// Returns true if a delimiter was completed, and removes it from `out`.
+        pub(crate) fn push(&mut self, out: &mut Vec<u8>, b: u8, delimiters: &[&[u8]], escape: Option<u8>) -> bool {
+            if self.escaping {
+                self.escaping = false;
+                out.push(b);
+                self.literal_len = out.len();
+                return false;
+            }
+            if escape == Some(b) {
+                self.escaping = true;
+                return false;
+            }
+            out.push(b);
+            let mut matched = 0usize;
+            for d in delimiters {
+                if d.len() > matched && out.len() >= self.literal_len + d.len() && out.ends_with(d) {
+                    matched = d.len();
+                }
+            }
+            if matched > 0 {
+                out.truncate(out.len() - matched);
+                return true;
+            }
+            return false;
+        }
+    }
+
+    pub(crate) fn eof(out: Vec<u8>, state: &State, strict: bool) -> std::io::Result<Vec<u8>> {
+        if strict || state.escaping {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "End of data before delimiter"));
+        }
+        return Ok(out);
+    }
+
+    /// Prefix `escape` to every byte that is `escape` or the start of a delimiter.
+    pub fn escape(data: &[u8], delimiters: &[&[u8]], escape: u8) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for b in data {
+            if *b == escape || delimiters.iter().any(|d| d.first() == Some(b)) {
+                out.push(escape);
+            }
+            out.push(*b);
+        }
+        return out;
+    }
+
+    /// Returns true if reading `data` followed by `delimiter` wouldn't end with exactly
+    /// `delimiter`: some delimiter ends before the end of `delimiter`, or a longer
+    /// one ends with it (the longest wins).
+    pub fn contains_delimiter(data: &[u8], delimiters: &[&[u8]], delimiter: &[u8]) -> bool {
+        let mut joined = data.to_vec();
+        joined.extend_from_slice(delimiter);
+        for d in delimiters {
+            if d.is_empty() {
+                continue;
+            }
+            for (start, w) in joined.windows(d.len()).enumerate() {
+                if w == *d && (start + d.len() < joined.len() || d.len() > delimiter.len()) {
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+}
+
+pub mod framing {
+    fn invalid(text: &'static str) -> std::io::Error {
+        return std::io::Error::new(std::io::ErrorKind::InvalidData, text);
+    }
+
+    /// Remove leading and trailing delimiters from data from `read_frame`.
+    pub fn trim_frame(frame: &[u8], delimiter: u8) -> &[u8] {
+        let start = frame.iter().position(|b| *b != delimiter).unwrap_or(frame.len());
+        let end = frame.iter().rposition(|b| *b != delimiter).map(|i| i + 1).unwrap_or(start);
+        return &frame[start .. end];
+    }
+
+    pub const COBS_DELIMITER: u8 = 0x00;
+
+    /// Consistent overhead byte stuffing, without the trailing delimiter
+    pub fn cobs_encode(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0u8];
+        let mut code_at = 0usize;
+        for b in data {
+            if *b == 0 {
+                out[code_at] = (out.len() - code_at) as u8;
+                code_at = out.len();
+                out.push(0);
+            } else {
+                out.push(*b);
+                if out.len() - code_at == 0xFF {
+                    out[code_at] = 0xFF;
+                    code_at = out.len();
+                    out.push(0);
+                }
+            }
+        }
+        out[code_at] = (out.len() - code_at) as u8;
+        return out;
+    }
+
+    pub fn cobs_decode(data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        let mut i = 0usize;
+        while i < data.len() {
+            let code = data[i] as usize;
+            if code == 0 {
+                return Err(invalid("Zero byte in COBS frame"));
+            }
+            if i + code > data.len() {
+                return Err(invalid("COBS block extends past end of frame"));
+            }
+            out.extend_from_slice(&data[i + 1 .. i + code]);
+            i += code;
+            if code != 0xFF && i < data.len() {
+                out.push(0);
+            }
+        }
+        return Ok(out);
+    }
+
+    pub const SLIP_END: u8 = 0xC0;
+    const SLIP_ESC: u8 = 0xDB;
+    const SLIP_ESC_END: u8 = 0xDC;
+    const SLIP_ESC_ESC: u8 = 0xDD;
+
+    /// SLIP (RFC 1055) escaping, without the trailing delimiter
+    pub fn slip_encode(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        for b in data {
+            match *b {
+                SLIP_END => out.extend([SLIP_ESC, SLIP_ESC_END]),
+                SLIP_ESC => out.extend([SLIP_ESC, SLIP_ESC_ESC]),
+                b => out.push(b),
+            }
+        }
+        return out;
+    }
+
+    pub fn slip_decode(data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        let mut iter = data.iter();
+        while let Some(b) = iter.next() {
+            if *b != SLIP_ESC {
+                out.push(*b);
+                continue;
+            }
+            match iter.next() {
+                Some(&SLIP_ESC_END) => out.push(SLIP_END),
+                Some(&SLIP_ESC_ESC) => out.push(SLIP_ESC),
+                _ => return Err(invalid("Invalid SLIP escape sequence")),
+            }
+        }
+        return Ok(out);
+    }
+
+    pub const HDLC_FLAG: u8 = 0x7E;
+    const HDLC_ESC: u8 = 0x7D;
+    const HDLC_XOR: u8 = 0x20;
+
+    /// HDLC-style (RFC 1662) byte stuffing, without the flags
+    pub fn hdlc_encode(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        for b in data {
+            match *b {
+                HDLC_FLAG | HDLC_ESC => out.extend([HDLC_ESC, *b ^ HDLC_XOR]),
+                b => out.push(b),
+            }
+        }
+        return out;
+    }
+
+    pub fn hdlc_decode(data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        let mut iter = data.iter();
+        while let Some(b) = iter.next() {
+            if *b != HDLC_ESC {
+                out.push(*b);
+                continue;
+            }
+            match iter.next() {
+                Some(b) => out.push(*b ^ HDLC_XOR),
+                None => return Err(invalid("HDLC escape at end of frame")),
+            }
+        }
+        return Ok(out);
+    }
+}
+
+pub mod codec {
+    /// A reversible transformation of a byte sequence, like compression.  Implement
+    /// this for custom codecs.
+    pub trait Codec {
+        /// Decoding must fail rather than produce more than `max_size` bytes.
+        fn decode(&self, data: &[u8], max_size: usize) -> std::io::Result<Vec<u8>>;
+        fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>>;
+    }
+
+    fn too_large(max_size: usize) -> std::io::Error {
+        return std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Decoded data is larger than the maximum of {} bytes", max_size),
+        );
+    }
+
+    /// Read all the output of a decoder, failing if there's more than `max_size`
+    /// bytes.
+    #[cfg(any(feature = "zlib", feature = "deflate", feature = "gzip", feature = "lz4", feature = "zstd"))]
+    fn read_limited(source: impl std::io::Read, max_size: usize) -> std::io::Result<Vec<u8>> {
+        let mut out = vec![];
+        std::io::Read::read_to_end(
+            &mut std::io::Read::take(source, (max_size as u64).saturating_add(1)),
+            &mut out,
+        )?;
+        if out.len() > max_size {
+            return Err(too_large(max_size));
+        }
+        return Ok(out);
+    }
+
+    /// Xor with a repeating key
+    pub struct Xor<'a>(pub &'a [u8]);
+
+    impl<'a> Codec for Xor<'a> {
+        fn decode(&self, data: &[u8], max_size: usize) -> std::io::Result<Vec<u8>> {
+            if data.len() > max_size {
+                return Err(too_large(max_size));
+            }
+            return self.encode(data);
+        }
+
+        fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            if self.0.is_empty() {
+                return Ok(data.to_vec());
+            }
+            return Ok(data.iter().zip(self.0.iter().cycle()).map(|(d, k)| d ^ k).collect());
+        }
+    }
+
+    /// Zlib (RFC 1950)
+    #[cfg(feature = "zlib")]
+    pub struct Zlib;
+
+    #[cfg(feature = "zlib")]
+    impl Codec for Zlib {
+        fn decode(&self, data: &[u8], max_size: usize) -> std::io::Result<Vec<u8>> {
+            return read_limited(flate2::read::ZlibDecoder::new(data), max_size);
+        }
+
+        fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            let mut e = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+            std::io::Write::write_all(&mut e, data)?;
+            return e.finish();
+        }
+    }
+
+    /// Raw deflate (RFC 1951)
+    #[cfg(feature = "deflate")]
+    pub struct Deflate;
+
+    #[cfg(feature = "deflate")]
+    impl Codec for Deflate {
+        fn decode(&self, data: &[u8], max_size: usize) -> std::io::Result<Vec<u8>> {
+            return read_limited(flate2::read::DeflateDecoder::new(data), max_size);
+        }
+
+        fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            let mut e = flate2::write::DeflateEncoder::new(vec![], flate2::Compression::default());
+            std::io::Write::write_all(&mut e, data)?;
+            return e.finish();
+        }
+    }
+
+    /// Gzip (RFC 1952)
+    #[cfg(feature = "gzip")]
+    pub struct Gzip;
+
+    #[cfg(feature = "gzip")]
+    impl Codec for Gzip {
+        fn decode(&self, data: &[u8], max_size: usize) -> std::io::Result<Vec<u8>> {
+            return read_limited(flate2::read::GzDecoder::new(data), max_size);
+        }
+
+        fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            let mut e = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            std::io::Write::write_all(&mut e, data)?;
+            return e.finish();
+        }
+    }
+
+    /// LZ4 frame format
+    #[cfg(feature = "lz4")]
+    pub struct Lz4;
+
+    #[cfg(feature = "lz4")]
+    impl Codec for Lz4 {
+        fn decode(&self, data: &[u8], max_size: usize) -> std::io::Result<Vec<u8>> {
+            return read_limited(lz4_flex::frame::FrameDecoder::new(data), max_size);
+        }
+
+        fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            let mut e = lz4_flex::frame::FrameEncoder::new(vec![]);
+            std::io::Write::write_all(&mut e, data)?;
+            return e.finish().map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+        }
+    }
+
+    /// Zstandard
+    #[cfg(feature = "zstd")]
+    pub struct Zstd;
+
+    #[cfg(feature = "zstd")]
+    impl Codec for Zstd {
+        fn decode(&self, data: &[u8], max_size: usize) -> std::io::Result<Vec<u8>> {
+            return read_limited(zstd::stream::read::Decoder::new(data)?, max_size);
+        }
+
+        fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            return zstd::stream::encode_all(data, 0);
+        }
+    }
+}
+
+pub mod checksum {
+    /// A checksum computed incrementally over a sequence of bytes.  Implement this
+    /// for custom algorithms.  `Value` should be an unsigned integer, it's stored
+    /// using the endianness specified in the schema.
+    pub trait Checksum: Default {
+        type Value: PartialEq + Copy + std::fmt::LowerHex;
+
+        fn update(&mut self, data: &[u8]);
+        fn finish(&self) -> Self::Value;
+    }
+
+    /// CRC-32 (ISO-HDLC, as used by zip, png, ethernet)
+    pub struct Crc32(u32);
+
+    impl Default for Crc32 {
+        fn default() -> Self {
+            return Self(0xFFFFFFFF);
+        }
+    }
+
+    impl Checksum for Crc32 {
+        type Value = u32;
+
+        fn update(&mut self, data: &[u8]) {
+            for b in data {
+                self.0 ^= *b as u32;
+                for _ in 0 .. 8 {
+                    if self.0 & 1 == 1 {
+                        self.0 = (self.0 >> 1) ^ 0xEDB88320;
+                    } else {
+                        self.0 >>= 1;
+                    }
+                }
+            }
+        }
+
+        fn finish(&self) -> u32 {
+            return self.0 ^ 0xFFFFFFFF;
+        }
+    }
+
+    /// Adler-32 (zlib)
+    pub struct Adler32 {
+        a: u32,
+        b: u32,
+    }
+
+    impl Default for Adler32 {
+        fn default() -> Self {
+            return Self {
+                a: 1,
+                b: 0,
+            };
+        }
+    }
+
+    impl Checksum for Adler32 {
+        type Value = u32;
+
+        fn update(&mut self, data: &[u8]) {
+            for b in data {
+                self.a = (self.a + *b as u32) % 65521;
+                self.b = (self.b + self.a) % 65521;
+            }
+        }
+
+        fn finish(&self) -> u32 {
+            return (self.b << 16) | self.a;
+        }
+    }
+
+    /// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF, not reflected)
+    pub struct Crc16Ccitt(u16);
+
+    impl Default for Crc16Ccitt {
+        fn default() -> Self {
+            return Self(0xFFFF);
+        }
+    }
+
+    impl Checksum for Crc16Ccitt {
+        type Value = u16;
+
+        fn update(&mut self, data: &[u8]) {
+            for b in data {
+                self.0 ^= (*b as u16) << 8;
+                for _ in 0 .. 8 {
+                    if self.0 & 0x8000 != 0 {
+                        self.0 = (self.0 << 1) ^ 0x1021;
+                    } else {
+                        self.0 <<= 1;
+                    }
+                }
+            }
+        }
+
+        fn finish(&self) -> u16 {
+            return self.0;
+        }
+    }
+
+    /// Internet checksum (RFC 1071), the ones' complement of the ones' complement sum
+    /// of big endian 16-bit words.  An odd trailing byte is padded with zero.
+    #[derive(Default)]
+    pub struct Internet {
+        sum: u32,
+        odd: Option<u8>,
+    }
+
+    impl Checksum for Internet {
+        type Value = u16;
+
+        fn update(&mut self, data: &[u8]) {
+            for b in data {
+                match self.odd.take() {
+                    Some(high) => {
+                        self.sum += u16::from_be_bytes([high, *b]) as u32;
+                        self.sum = (self.sum & 0xFFFF) + (self.sum >> 16);
+                    },
+                    None => {
+                        self.odd = Some(*b);
+                    },
+                }
+            }
+        }
+
+        fn finish(&self) -> u16 {
+            let mut sum = self.sum;
+            if let Some(high) = self.odd {
+                sum += u16::from_be_bytes([high, 0]) as u32;
+                sum = (sum & 0xFFFF) + (sum >> 16);
+            }
+            return !(sum as u16);
+        }
+    }
+
+    /// All bytes xored together
+    #[derive(Default)]
+    pub struct Xor(u8);
+
+    impl Checksum for Xor {
+        type Value = u8;
+
+        fn update(&mut self, data: &[u8]) {
+            for b in data {
+                self.0 ^= *b;
+            }
+        }
+
+        fn finish(&self) -> u8 {
+            return self.0;
+        }
+    }
+
+    impl<C: Checksum> crate::Observe for C {
+        fn observe(&mut self, data: &[u8]) {
+            self.update(data);
+        }
+    }
+}
+
+/// Traits implemented by generated types that are the rust root of a top level
+/// scope, for writing generic code over schemas.
+pub mod traits {
+    pub trait Decode: Sized {
+        type Error: std::error::Error;
+
+        fn decode<R: std::io::BufRead>(source: &mut R) -> Result<Self, Self::Error>;
+
+        /// Read a value from the start of `data`.
+        fn from_bytes(data: &[u8]) -> Result<Self, Self::Error> {
+            return Self::decode(&mut std::io::Cursor::new(data));
+        }
+    }
+
+    pub trait Encode: Sized {
+        fn encode<W: std::io::Write>(self, dest: &mut W) -> std::io::Result<()>;
+
+        fn to_vec(self) -> std::io::Result<Vec<u8>> {
+            let mut out = vec![];
+            self.encode(&mut out)?;
+            return Ok(out);
+        }
+
+        /// Write the value to the start of `dest`, returning the number of bytes
+        /// written.  Returns an error if `dest` is too small.
+        fn write_to(self, dest: &mut [u8]) -> std::io::Result<usize> {
+            let mut cursor = std::io::Cursor::new(dest);
+            self.encode(&mut cursor)?;
+            return Ok(cursor.position() as usize);
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub trait AsyncDecode: Sized {
+        type Error: std::error::Error;
+
+        fn decode_async<
+            R: crate::async_::AsyncBufRead + Unpin,
+        >(source: &mut R) -> impl std::future::Future<Output = Result<Self, Self::Error>>;
+    }
+
+    #[cfg(feature = "async")]
+    pub trait AsyncEncode: Sized {
+        fn encode_async<
+            W: crate::async_::AsyncWrite + Unpin,
+        >(self, dest: &mut W) -> impl std::future::Future<Output = std::io::Result<()>>;
+    }
+
+    /// A hand-written type that reads and writes its own segment of the stream, for
+    /// use with `Scope::delegate` in the generator.  `offset` is the stream offset at
+    /// the start of the segment.  When writing it's only known in top level scopes,
+    /// whose preceding segments are written first; values in nested scopes (array
+    /// elements, optional values, etc.) are written before the data around them, so
+    /// there it's `None`.
+    pub trait Delegate: Sized {
+        fn read<R: std::io::Read>(source: &mut R, offset: usize) -> std::io::Result<Self>;
+        fn write<W: std::io::Write>(self, dest: &mut W, offset: Option<usize>) -> std::io::Result<()>;
+    }
+
+    #[cfg(feature = "async")]
+    pub trait AsyncDelegate: Sized {
+        fn read_async<
+            R: crate::async_::AsyncRead + Unpin,
+        >(source: &mut R, offset: usize) -> impl std::future::Future<Output = std::io::Result<Self>>;
+        fn write_async<
+            W: crate::async_::AsyncWrite + Unpin,
+        >(self, dest: &mut W, offset: Option<usize>) -> impl std::future::Future<Output = std::io::Result<()>>;
+    }
 }
 
 pub mod lowheap_error {
@@ -109,12 +862,23 @@ pub mod lowheap_error {
     #[derive(Debug)]
     pub struct ReadError {
         pub node: &'static str,
+        /// The index of the innermost array element being read when the error
+        /// occurred, if any
+        pub index: Option<usize>,
         pub inner: ReadErrorInner,
     }
 
     impl Display for ReadError {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            return format_args!("Error reading in node {}: {:?}", self.node, self.inner).fmt(f);
+            match self.index {
+                Some(index) => return format_args!(
+                    "Error reading in node {} (element {}): {:?}",
+                    self.node,
+                    index,
+                    self.inner
+                ).fmt(f),
+                None => return format_args!("Error reading in node {}: {:?}", self.node, self.inner).fmt(f),
+            }
         }
     }
 
@@ -124,9 +888,85 @@ pub mod lowheap_error {
         pub fn new(node: &'static str, text: &'static str) -> ReadError {
             return ReadError {
                 node: node,
+                index: None,
                 inner: ReadErrorInner::Other(text),
             };
         }
+
+        /// Record the index of the array element being read, unless an inner array
+        /// already recorded one.
+        pub fn in_element(mut self, index: usize) -> ReadError {
+            if self.index.is_none() {
+                self.index = Some(index);
+            }
+            return self;
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum WriteErrorInner {
+        Io(std::io::Error),
+        Other(&'static str),
+    }
+
+    impl From<std::io::Error> for WriteErrorInner {
+        fn from(value: std::io::Error) -> Self {
+            return WriteErrorInner::Io(value);
+        }
+    }
+
+    impl From<&'static str> for WriteErrorInner {
+        fn from(value: &'static str) -> Self {
+            return WriteErrorInner::Other(value);
+        }
+    }
+
+    /// A value couldn't be written.  Write functions return this wrapped in a
+    /// `std::io::Error` (retrieve it with `get_ref` and `downcast_ref`).
+    #[derive(Debug)]
+    pub struct WriteError {
+        pub node: &'static str,
+        pub inner: WriteErrorInner,
+    }
+
+    impl Display for WriteError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            return format_args!("Error writing in node {}: {:?}", self.node, self.inner).fmt(f);
+        }
+    }
+
+    impl std::error::Error for WriteError { }
+
+    impl WriteError {
+        pub fn new(node: &'static str, inner: impl Into<WriteErrorInner>) -> WriteError {
+            return WriteError {
+                node: node,
+                inner: inner.into(),
+            };
+        }
+    }
+
+    impl From<WriteError> for std::io::Error {
+        fn from(value: WriteError) -> Self {
+            let kind = match &value.inner {
+                WriteErrorInner::Io(e) => e.kind(),
+                WriteErrorInner::Other(_) => std::io::ErrorKind::InvalidInput,
+            };
+            return std::io::Error::new(kind, value);
+        }
+    }
+
+    pub trait WriteErrCtx<T> {
+        fn errorize_write(self, node: &'static str, text: &'static str) -> std::io::Result<T>;
+    }
+
+    impl<T, E> WriteErrCtx<T> for Result<T, E> {
+        fn errorize_write(self, node: &'static str, text: &'static str) -> std::io::Result<T> {
+            match self {
+                Err(_) => return Err(WriteError::new(node, text).into()),
+                Ok(v) => return Ok(v),
+            }
+        }
     }
 
     pub trait ReadErrCtx<T> {
@@ -138,6 +978,7 @@ pub mod lowheap_error {
             match self {
                 Err(_) => return Err(ReadError {
                     node: node,
+                    index: None,
                     inner: ReadErrorInner::Other(text),
                 }),
                 Ok(v) => return Ok(v),
@@ -154,6 +995,7 @@ pub mod lowheap_error {
             match self {
                 Err(e) => return Err(ReadError {
                     node: node,
+                    index: None,
                     inner: ReadErrorInner::Io(e),
                 }),
                 Ok(v) => return Ok(v),
@@ -175,12 +1017,23 @@ pub mod error {
     #[derive(Debug)]
     pub struct ReadError {
         pub node: &'static str,
+        /// The index of the innermost array element being read when the error
+        /// occurred, if any
+        pub index: Option<usize>,
         pub inner: ReadErrorInner,
     }
 
     impl Display for ReadError {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            return format_args!("Error reading in node {}: {:?}", self.node, self.inner).fmt(f);
+            match self.index {
+                Some(index) => return format_args!(
+                    "Error reading in node {} (element {}): {:?}",
+                    self.node,
+                    index,
+                    self.inner
+                ).fmt(f),
+                None => return format_args!("Error reading in node {}: {:?}", self.node, self.inner).fmt(f),
+            }
         }
     }
 
@@ -190,9 +1043,91 @@ pub mod error {
         pub fn new(node: &'static str, text: impl Into<String>) -> ReadError {
             return ReadError {
                 node: node,
+                index: None,
                 inner: ReadErrorInner::Other(text.into()),
             };
         }
+
+        /// Record the index of the array element being read, unless an inner array
+        /// already recorded one.
+        pub fn in_element(mut self, index: usize) -> ReadError {
+            if self.index.is_none() {
+                self.index = Some(index);
+            }
+            return self;
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum WriteErrorInner {
+        Io(std::io::Error),
+        Other(String),
+    }
+
+    impl From<std::io::Error> for WriteErrorInner {
+        fn from(value: std::io::Error) -> Self {
+            return WriteErrorInner::Io(value);
+        }
+    }
+
+    impl From<String> for WriteErrorInner {
+        fn from(value: String) -> Self {
+            return WriteErrorInner::Other(value);
+        }
+    }
+
+    impl From<&str> for WriteErrorInner {
+        fn from(value: &str) -> Self {
+            return WriteErrorInner::Other(value.to_string());
+        }
+    }
+
+    /// A value couldn't be written.  Write functions return this wrapped in a
+    /// `std::io::Error` (retrieve it with `get_ref` and `downcast_ref`).
+    #[derive(Debug)]
+    pub struct WriteError {
+        pub node: &'static str,
+        pub inner: WriteErrorInner,
+    }
+
+    impl Display for WriteError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            return format_args!("Error writing in node {}: {:?}", self.node, self.inner).fmt(f);
+        }
+    }
+
+    impl std::error::Error for WriteError { }
+
+    impl WriteError {
+        pub fn new(node: &'static str, inner: impl Into<WriteErrorInner>) -> WriteError {
+            return WriteError {
+                node: node,
+                inner: inner.into(),
+            };
+        }
+    }
+
+    impl From<WriteError> for std::io::Error {
+        fn from(value: WriteError) -> Self {
+            let kind = match &value.inner {
+                WriteErrorInner::Io(e) => e.kind(),
+                WriteErrorInner::Other(_) => std::io::ErrorKind::InvalidInput,
+            };
+            return std::io::Error::new(kind, value);
+        }
+    }
+
+    pub trait WriteErrCtx<T> {
+        fn errorize_write(self, node: &'static str) -> std::io::Result<T>;
+    }
+
+    impl<T, E: Display> WriteErrCtx<T> for Result<T, E> {
+        fn errorize_write(self, node: &'static str) -> std::io::Result<T> {
+            match self {
+                Err(e) => return Err(WriteError::new(node, e.to_string()).into()),
+                Ok(v) => return Ok(v),
+            }
+        }
     }
 
     pub trait ReadErrCtx<T> {
@@ -204,6 +1139,7 @@ pub mod error {
             match self {
                 Err(e) => return Err(ReadError {
                     node: node,
+                    index: None,
                     inner: ReadErrorInner::Other(e.to_string()),
                 }),
                 Ok(v) => return Ok(v),
@@ -220,6 +1156,7 @@ pub mod error {
             match self {
                 Err(e) => return Err(ReadError {
                     node: node,
+                    index: None,
                     inner: ReadErrorInner::Io(e),
                 }),
                 Ok(v) => return Ok(v),
@@ -11,4 +11,19 @@ pub mod node_align;
 pub mod node_delimited_bytes;
 pub mod node_remaining_bytes;
 pub mod node_custom;
+pub mod node_optional;
+pub mod node_int_transform;
+pub mod node_fan_out;
+pub mod node_checksum;
+pub mod node_capture;
+pub mod node_decode;
+pub mod node_framed;
+pub mod node_tlv;
+pub mod node_stride_array;
+pub mod node_dynamic_map;
+pub mod node_array_external;
+pub mod node_array_index;
+pub mod node_instance;
+pub mod node_delegate;
+pub mod node_plugin;
 pub mod node;
@@ -31,6 +31,25 @@ use crate::{
         node_remaining_bytes::NodeRemainingBytes,
         node_custom::NodeCustom,
         node_align::NodeAlign,
+        node_optional::NodeOptional,
+        node_int_transform::NodeIntTransform,
+        node_fan_out::NodeFanOut,
+        node_checksum::NodeChecksum,
+        node_capture::NodeCapture,
+        node_capture::NodeCaptureRaw,
+        node_decode::NodeDecode,
+        node_framed::NodeFramed,
+        node_tlv::NodeTlv,
+        node_stride_array::NodeStrideArray,
+        node_dynamic_map::NodeDynamicMap,
+        node_dynamic_map::NodeMapKey,
+        node_dynamic_array::NodePrevious,
+        node_array_external::NodeArrayExternal,
+        node_array_index::NodeArrayIndex,
+        node_instance::NodeInstance,
+        node_instance::NodeParam,
+        node_delegate::NodeDelegate,
+        node_plugin::NodePlugin,
     },
     schema::GenerateContext,
     scope::Scope,
@@ -111,6 +130,25 @@ pub(crate) enum Node_ {
     Custom(NodeCustom),
     ObjField(NodeObjField),
     Obj(NodeObj),
+    Optional(NodeOptional),
+    IntTransform(NodeIntTransform),
+    FanOut(NodeFanOut),
+    Checksum(NodeChecksum),
+    Capture(NodeCapture),
+    CaptureRaw(NodeCaptureRaw),
+    Decode(NodeDecode),
+    Framed(NodeFramed),
+    Tlv(NodeTlv),
+    StrideArray(NodeStrideArray),
+    DynamicMap(NodeDynamicMap),
+    MapKey(NodeMapKey),
+    Previous(NodePrevious),
+    ArrayExternal(NodeArrayExternal),
+    ArrayIndex(NodeArrayIndex),
+    Instance(NodeInstance),
+    Param(NodeParam),
+    Delegate(NodeDelegate),
+    Plugin(NodePlugin),
 }
 
 impl NodeMethods for Node_ {
@@ -133,6 +171,25 @@ impl NodeMethods for Node_ {
             Node_::Custom(inner) => NodeMethods::gather_read_deps(inner),
             Node_::ObjField(inner) => NodeMethods::gather_read_deps(inner),
             Node_::Obj(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Optional(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::IntTransform(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::FanOut(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Checksum(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Capture(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::CaptureRaw(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Decode(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Framed(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Tlv(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::StrideArray(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::DynamicMap(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::MapKey(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Previous(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::ArrayExternal(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::ArrayIndex(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Instance(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Param(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Delegate(inner) => NodeMethods::gather_read_deps(inner),
+            Node_::Plugin(inner) => NodeMethods::gather_read_deps(inner),
         }
     }
 
@@ -187,6 +244,63 @@ impl NodeMethods for Node_ {
             Node_::Obj(inner) => {
                 NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
             },
+            Node_::Optional(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::IntTransform(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::FanOut(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Checksum(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Capture(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::CaptureRaw(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Decode(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Framed(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Tlv(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::StrideArray(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::DynamicMap(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::MapKey(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Previous(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::ArrayExternal(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::ArrayIndex(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Instance(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Param(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Delegate(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Plugin(inner) => {
+                NodeMethods::generate_read(inner, __enum_dispatch_arg_0)
+            },
         }
     }
 
@@ -209,6 +323,25 @@ impl NodeMethods for Node_ {
             Node_::Custom(inner) => NodeMethods::gather_write_deps(inner),
             Node_::ObjField(inner) => NodeMethods::gather_write_deps(inner),
             Node_::Obj(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Optional(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::IntTransform(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::FanOut(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Checksum(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Capture(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::CaptureRaw(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Decode(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Framed(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Tlv(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::StrideArray(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::DynamicMap(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::MapKey(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Previous(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::ArrayExternal(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::ArrayIndex(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Instance(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Param(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Delegate(inner) => NodeMethods::gather_write_deps(inner),
+            Node_::Plugin(inner) => NodeMethods::gather_write_deps(inner),
         }
     }
 
@@ -263,6 +396,63 @@ impl NodeMethods for Node_ {
             Node_::Obj(inner) => {
                 NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
             },
+            Node_::Optional(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::IntTransform(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::FanOut(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Checksum(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Capture(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::CaptureRaw(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Decode(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Framed(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Tlv(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::StrideArray(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::DynamicMap(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::MapKey(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Previous(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::ArrayExternal(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::ArrayIndex(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Instance(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Param(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Delegate(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Plugin(inner) => {
+                NodeMethods::generate_write(inner, __enum_dispatch_arg_0)
+            },
         }
     }
 
@@ -311,6 +501,63 @@ impl NodeMethods for Node_ {
             Node_::Obj(inner) => {
                 NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
             },
+            Node_::Optional(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::IntTransform(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::FanOut(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Checksum(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Capture(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::CaptureRaw(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Decode(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Framed(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Tlv(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::StrideArray(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::DynamicMap(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::MapKey(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Previous(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::ArrayExternal(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::ArrayIndex(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Instance(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Param(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Delegate(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
+            Node_::Plugin(inner) => {
+                NodeMethods::set_rust(inner, __enum_dispatch_arg_0)
+            },
         }
     }
 
@@ -333,6 +580,25 @@ impl NodeMethods for Node_ {
             Node_::Custom(inner) => NodeMethods::scope(inner),
             Node_::ObjField(inner) => NodeMethods::scope(inner),
             Node_::Obj(inner) => NodeMethods::scope(inner),
+            Node_::Optional(inner) => NodeMethods::scope(inner),
+            Node_::IntTransform(inner) => NodeMethods::scope(inner),
+            Node_::FanOut(inner) => NodeMethods::scope(inner),
+            Node_::Checksum(inner) => NodeMethods::scope(inner),
+            Node_::Capture(inner) => NodeMethods::scope(inner),
+            Node_::CaptureRaw(inner) => NodeMethods::scope(inner),
+            Node_::Decode(inner) => NodeMethods::scope(inner),
+            Node_::Framed(inner) => NodeMethods::scope(inner),
+            Node_::Tlv(inner) => NodeMethods::scope(inner),
+            Node_::StrideArray(inner) => NodeMethods::scope(inner),
+            Node_::DynamicMap(inner) => NodeMethods::scope(inner),
+            Node_::MapKey(inner) => NodeMethods::scope(inner),
+            Node_::Previous(inner) => NodeMethods::scope(inner),
+            Node_::ArrayExternal(inner) => NodeMethods::scope(inner),
+            Node_::ArrayIndex(inner) => NodeMethods::scope(inner),
+            Node_::Instance(inner) => NodeMethods::scope(inner),
+            Node_::Param(inner) => NodeMethods::scope(inner),
+            Node_::Delegate(inner) => NodeMethods::scope(inner),
+            Node_::Plugin(inner) => NodeMethods::scope(inner),
         }
     }
 
@@ -355,6 +621,25 @@ impl NodeMethods for Node_ {
             Node_::Custom(inner) => NodeMethods::id(inner),
             Node_::ObjField(inner) => NodeMethods::id(inner),
             Node_::Obj(inner) => NodeMethods::id(inner),
+            Node_::Optional(inner) => NodeMethods::id(inner),
+            Node_::IntTransform(inner) => NodeMethods::id(inner),
+            Node_::FanOut(inner) => NodeMethods::id(inner),
+            Node_::Checksum(inner) => NodeMethods::id(inner),
+            Node_::Capture(inner) => NodeMethods::id(inner),
+            Node_::CaptureRaw(inner) => NodeMethods::id(inner),
+            Node_::Decode(inner) => NodeMethods::id(inner),
+            Node_::Framed(inner) => NodeMethods::id(inner),
+            Node_::Tlv(inner) => NodeMethods::id(inner),
+            Node_::StrideArray(inner) => NodeMethods::id(inner),
+            Node_::DynamicMap(inner) => NodeMethods::id(inner),
+            Node_::MapKey(inner) => NodeMethods::id(inner),
+            Node_::Previous(inner) => NodeMethods::id(inner),
+            Node_::ArrayExternal(inner) => NodeMethods::id(inner),
+            Node_::ArrayIndex(inner) => NodeMethods::id(inner),
+            Node_::Instance(inner) => NodeMethods::id(inner),
+            Node_::Param(inner) => NodeMethods::id(inner),
+            Node_::Delegate(inner) => NodeMethods::id(inner),
+            Node_::Plugin(inner) => NodeMethods::id(inner),
         }
     }
 
@@ -377,6 +662,25 @@ impl NodeMethods for Node_ {
             Node_::Custom(inner) => NodeMethods::id_ident(inner),
             Node_::ObjField(inner) => NodeMethods::id_ident(inner),
             Node_::Obj(inner) => NodeMethods::id_ident(inner),
+            Node_::Optional(inner) => NodeMethods::id_ident(inner),
+            Node_::IntTransform(inner) => NodeMethods::id_ident(inner),
+            Node_::FanOut(inner) => NodeMethods::id_ident(inner),
+            Node_::Checksum(inner) => NodeMethods::id_ident(inner),
+            Node_::Capture(inner) => NodeMethods::id_ident(inner),
+            Node_::CaptureRaw(inner) => NodeMethods::id_ident(inner),
+            Node_::Decode(inner) => NodeMethods::id_ident(inner),
+            Node_::Framed(inner) => NodeMethods::id_ident(inner),
+            Node_::Tlv(inner) => NodeMethods::id_ident(inner),
+            Node_::StrideArray(inner) => NodeMethods::id_ident(inner),
+            Node_::DynamicMap(inner) => NodeMethods::id_ident(inner),
+            Node_::MapKey(inner) => NodeMethods::id_ident(inner),
+            Node_::Previous(inner) => NodeMethods::id_ident(inner),
+            Node_::ArrayExternal(inner) => NodeMethods::id_ident(inner),
+            Node_::ArrayIndex(inner) => NodeMethods::id_ident(inner),
+            Node_::Instance(inner) => NodeMethods::id_ident(inner),
+            Node_::Param(inner) => NodeMethods::id_ident(inner),
+            Node_::Delegate(inner) => NodeMethods::id_ident(inner),
+            Node_::Plugin(inner) => NodeMethods::id_ident(inner),
         }
     }
 
@@ -399,6 +703,25 @@ impl NodeMethods for Node_ {
             Node_::Custom(inner) => NodeMethods::rust_type(inner),
             Node_::ObjField(inner) => NodeMethods::rust_type(inner),
             Node_::Obj(inner) => NodeMethods::rust_type(inner),
+            Node_::Optional(inner) => NodeMethods::rust_type(inner),
+            Node_::IntTransform(inner) => NodeMethods::rust_type(inner),
+            Node_::FanOut(inner) => NodeMethods::rust_type(inner),
+            Node_::Checksum(inner) => NodeMethods::rust_type(inner),
+            Node_::Capture(inner) => NodeMethods::rust_type(inner),
+            Node_::CaptureRaw(inner) => NodeMethods::rust_type(inner),
+            Node_::Decode(inner) => NodeMethods::rust_type(inner),
+            Node_::Framed(inner) => NodeMethods::rust_type(inner),
+            Node_::Tlv(inner) => NodeMethods::rust_type(inner),
+            Node_::StrideArray(inner) => NodeMethods::rust_type(inner),
+            Node_::DynamicMap(inner) => NodeMethods::rust_type(inner),
+            Node_::MapKey(inner) => NodeMethods::rust_type(inner),
+            Node_::Previous(inner) => NodeMethods::rust_type(inner),
+            Node_::ArrayExternal(inner) => NodeMethods::rust_type(inner),
+            Node_::ArrayIndex(inner) => NodeMethods::rust_type(inner),
+            Node_::Instance(inner) => NodeMethods::rust_type(inner),
+            Node_::Param(inner) => NodeMethods::rust_type(inner),
+            Node_::Delegate(inner) => NodeMethods::rust_type(inner),
+            Node_::Plugin(inner) => NodeMethods::rust_type(inner),
         }
     }
 }
@@ -0,0 +1,228 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        LateInit,
+        ToIdent,
+    },
+    node::node::{
+        Node,
+        NodeMethods,
+        RedirectRef,
+        ToDep,
+        Node_,
+    },
+    derive_forward_node_methods,
+    schema::GenerateContext,
+    scope::{
+        Scope,
+        EscapableParent,
+    },
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeArrayExternalMut_ {
+    /// Connected to the array node in the outer scope
+    pub(crate) outer: LateInit<RedirectRef<Node, Node>>,
+    pub(crate) rust: Option<Node>,
+}
+
+/// A copy of a value from outside an array, for use within each element.  When
+/// reading the outer value is copied; when writing the value from the first element
+/// is lifted out and every other element must match it.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeArrayExternal_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) mut_: GcCell<NodeArrayExternalMut_>,
+}
+
+impl NodeArrayExternal_ {
+    fn lifted_ident(&self) -> Ident {
+        return format!("{}__lifted", self.id).ident().unwrap();
+    }
+
+    fn outer(&self) -> Node {
+        return self.mut_.borrow().outer.as_ref().unwrap().primary.clone();
+    }
+}
+
+impl NodeMethods for NodeArrayExternal_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        // The array depends on the outer value
+        return vec![];
+    }
+
+    fn generate_read(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = self.outer().id_ident();
+        let dest_ident = &self.id_ident;
+        return quote!{
+            #dest_ident = #source_ident.clone();
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().rust.dep();
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let lifted_ident = self.lifted_ident();
+        let outer_id = self.outer().id();
+        let err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Value doesn't match value from other array elements",
+                quote!(format!("Value doesn't match value of {} from other array elements", #outer_id)),
+            );
+        return quote!{
+            if let Some(lifted__) = &#lifted_ident {
+                if * lifted__ != #source_ident {
+                    return Err(#err);
+                }
+            }
+            else {
+                #lifted_ident = Some(#source_ident.clone());
+            }
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.outer().rust_type();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub(crate) struct NodeArrayExternal(pub(crate) Gc<NodeArrayExternal_>);
+
+impl Into<Node> for NodeArrayExternal {
+    fn into(self) -> Node {
+        return Node(Node_::ArrayExternal(self));
+    }
+}
+
+derive_forward_node_methods!(NodeArrayExternal);
+
+/// Outer values used by elements, to be added to the array's read deps.
+pub(crate) fn array_external_read_deps(element: &Scope) -> Vec<Node> {
+    let mut out = vec![];
+    for external in &element.0.mut_.borrow().array_externals {
+        out.extend(external.0.mut_.borrow().outer.dep());
+    }
+    out.extend(element.0.mut_.borrow().observed_externals.iter().cloned());
+    return out;
+}
+
+/// The outer value's other uses are written first so the outer value can be used
+/// if the array is empty.  Observed outer values are also written first.
+pub(crate) fn array_external_write_deps(element: &Scope) -> Vec<Node> {
+    let mut out = vec![];
+    for observed in &element.0.mut_.borrow().observed_externals {
+        out.extend(observed.0.gather_write_deps());
+    }
+    for external in &element.0.mut_.borrow().array_externals {
+        if let Node_::FanOut(fan_out) = &external.0.outer().0 {
+            out.extend(fan_out.0.serial.0.gather_write_deps());
+        }
+    }
+    return out;
+}
+
+/// Returns code to run before and after the element loop when writing.
+pub(crate) fn generate_array_external_write(gen_ctx: &GenerateContext, element: &Scope) -> (TokenStream, TokenStream) {
+    let mut init = vec![];
+    let mut finish = vec![];
+    for external in &element.0.mut_.borrow().array_externals {
+        let lifted_ident = external.0.lifted_ident();
+        let rust_type = external.rust_type();
+        let outer = external.0.outer();
+        let outer_ident = outer.id_ident();
+        let fallback;
+        if let Node_::FanOut(fan_out) = &outer.0 {
+            let shared = &fan_out.0.serial;
+            let shared_id = shared.id();
+            let provided =
+                shared
+                    .scope()
+                    .0
+                    .mut_
+                    .borrow()
+                    .rust_connected
+                    .get(&shared_id)
+                    .map(|uses| uses.iter().any(|(_, _, n)| n.id() == shared_id))
+                    .unwrap_or(false);
+            if !provided {
+                let EscapableParent::Array(parent) = &element.0.mut_.borrow().escapable_parent else {
+                    unreachable!();
+                };
+                if let Node_::Optional(optional) = &parent.array.0 {
+                    panic!(
+                        "{} is only used in optional {}, so it can't be written if the value is None; also use it outside the optional (for example as an object field)",
+                        shared_id,
+                        optional.0.id
+                    );
+                }
+                panic!(
+                    "{} is only used in array elements, so it can't be written if the array is empty; also use it outside the array (for example as an object field)",
+                    shared_id
+                );
+            }
+            let shared_ident = shared.id_ident();
+            fallback = quote!(#shared_ident.clone());
+        } else {
+            let outer_id = outer.id();
+            let err =
+                gen_ctx.new_write_err(
+                    &external.0.id,
+                    "Array is empty, value can't be determined",
+                    quote!(format!("Array is empty so value of {} can't be determined", #outer_id)),
+                );
+            fallback = quote!(return Err(#err));
+        }
+        init.push(quote!{
+            let mut #lifted_ident: Option < #rust_type >= None;
+        });
+        finish.push(quote!{
+            #outer_ident = match #lifted_ident {
+                Some(v) => v,
+                None => {
+                    #fallback
+                },
+            };
+        });
+    }
+    return (quote!(#(#init) *), quote!(#(#finish) *));
+}
@@ -0,0 +1,135 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::ToIdent,
+    node::node::{
+        Node,
+        NodeMethods,
+        Node_,
+    },
+    derive_forward_node_methods,
+    schema::GenerateContext,
+    scope::Scope,
+};
+
+/// The index (`usize`) of the current element within an array element scope.  The
+/// array assigns it before each element is processed, in both directions.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeArrayIndex_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+}
+
+impl NodeArrayIndex_ {
+    fn next_ident(&self) -> Ident {
+        return format!("{}__next", self.id).ident().unwrap();
+    }
+}
+
+impl NodeMethods for NodeArrayIndex_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        return vec![];
+    }
+
+    fn generate_read(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        return quote!();
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return vec![];
+    }
+
+    fn generate_write(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        return quote!();
+    }
+
+    fn set_rust(&self, _rust: Node) {
+        // Known before the element is processed in both directions, so any number of
+        // consumers can use it directly
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return quote!(usize);
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeArrayIndex(pub(crate) Gc<NodeArrayIndex_>);
+
+impl Into<Node> for NodeArrayIndex {
+    fn into(self) -> Node {
+        return Node(Node_::ArrayIndex(self));
+    }
+}
+
+derive_forward_node_methods!(NodeArrayIndex);
+
+/// Returns code to run before the element loop and at the start of each element, in
+/// both directions.
+pub(crate) fn generate_array_index(element: &Scope) -> (TokenStream, TokenStream) {
+    let mut init = vec![];
+    let mut load = vec![];
+    for index in &element.0.mut_.borrow().array_indices {
+        let id_ident = &index.0.id_ident;
+        let next_ident = index.0.next_ident();
+        init.push(quote!{
+            let mut #next_ident: usize = 0;
+        });
+        load.push(quote!{
+            let #id_ident = #next_ident;
+            #next_ident += 1;
+        });
+    }
+    return (quote!(#(#init) *), quote!(#(#load) *));
+}
+
+/// Wraps the code reading one element so that read errors raised inside it carry the
+/// element index, from the `usize` variable `index_ident`.
+pub(crate) fn generate_element_read(gen_ctx: &GenerateContext, index_ident: &Ident, code: TokenStream) -> TokenStream {
+    let err_ident = gen_ctx.read_err_type();
+    let read;
+    if gen_ctx.async_ {
+        read = quote!{
+            async {
+                #code 
+                //. .
+                return Ok::<(),
+                #err_ident >(());
+            }.await
+        };
+    } else {
+        read = quote!{
+            (|| -> Result <(),
+            #err_ident > {
+                #code 
+                //. .
+                return Ok(());
+            })()
+        };
+    }
+    return quote!{
+        #read.map_err(| e | e.in_element(#index_ident)) ?;
+    };
+}
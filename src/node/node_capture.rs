@@ -0,0 +1,307 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        ToIdent,
+        rust_type_bytes,
+        offset_ident,
+    },
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            ToDep,
+            Node_,
+        },
+        node_serial::NodeSerialSegment,
+    },
+    derive_forward_node_methods,
+    schema::{
+        GenerateContext,
+        generate_nested_write,
+        generate_nested_read,
+        generate_read,
+    },
+    scope::{
+        Scope,
+        CaptureWrite,
+    },
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeCaptureMut_ {
+    pub(crate) rust: Option<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeCapture_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    pub(crate) element: Scope,
+    pub(crate) raw: NodeCaptureRaw,
+    #[unsafe_ignore_trace]
+    pub(crate) write_mode: CaptureWrite,
+    pub(crate) mut_: GcCell<NodeCaptureMut_>,
+}
+
+impl NodeCapture_ {
+    /// Generate a function that reads the element from raw bytes, for checking that
+    /// the raw bytes match the value before writing them verbatim.
+    fn generate_parse_raw(&self, gen_ctx: &GenerateContext) -> Ident {
+        let offset_ident = offset_ident();
+        let rust = self.element.get_rust_root();
+        let rust_ident = rust.id_ident();
+        let rust_type = rust.rust_type();
+        let serial_ident = &self.element.0.serial_root.0.id_ident;
+        let reader = gen_ctx.reader_bound(&self.scope.0.schema.0.borrow().reader_bounds);
+        let err_ident = gen_ctx.read_err_type();
+        let errors = gen_ctx.read_imports();
+        let method_code = generate_read(gen_ctx, &self.element);
+        return gen_ctx.add_fn(
+            false,
+            &format!("{}_raw", self.id),
+            serial_ident,
+            quote!(< R: #reader >),
+            vec![quote!(#serial_ident:& mut R)],
+            quote!(Result < #rust_type, #err_ident >),
+            quote!{
+                #errors 
+                //. .
+                let mut #offset_ident = 0usize;
+                let depth__ = 0usize;
+                #method_code 
+                //. .
+                return Ok(#rust_ident);
+            },
+        );
+    }
+}
+
+impl NodeMethods for NodeCapture_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.serial.dep());
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let dest_ident = &self.id_ident;
+        let raw_dest_ident = &self.raw.0.id_ident;
+        let elem_code = generate_nested_read(gen_ctx, &self.element);
+        let elem_dest_ident = self.element.get_rust_root().id_ident();
+        let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let inner_serial_ident = &self.element.0.serial_root.0.id_ident;
+        let reader_ident = "capture_read__".ident().unwrap();
+        return quote!{
+            {
+                let mut #reader_ident = inarybay_runtime:: ObserveRead:: new(
+                    &mut * #outer_serial_ident,
+                    std:: vec:: Vec::< u8 >:: new()
+                );
+                {
+                    let #inner_serial_ident =& mut #reader_ident;
+                    //. .
+                    #elem_code
+                    //. .
+                    #dest_ident = #elem_dest_ident;
+                }
+                #raw_dest_ident = Some(#reader_ident.observer);
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+        out.push(self.raw.clone().into());
+        return out;
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = &self.serial.0.id_ident;
+        let elem_code = generate_nested_write(gen_ctx, &self.element);
+        let elem_source_ident = self.element.get_rust_root().id_ident();
+        let elem_dest_ident = &self.element.0.serial_root.0.id_ident;
+        let reserialize = quote!{
+            let mut #elem_dest_ident = std:: vec:: Vec::< u8 >:: new();
+            #elem_code
+            //. .
+            #dest_ident.extend(#elem_dest_ident);
+        };
+        let write;
+        match self.write_mode {
+            CaptureWrite::Verbatim if self.raw.0.mut_.borrow().rust.is_some() => {
+                let raw_source_ident = &self.raw.0.id_ident;
+                let verbatim_ident = format!("{}__verbatim", self.id).ident().unwrap();
+                let reader_ident = format!("{}__raw_reader", self.id).ident().unwrap();
+                let cursor;
+                if gen_ctx.async_ {
+                    cursor = quote!(inarybay_runtime:: async_:: Cursor);
+                } else {
+                    cursor = quote!(std:: io:: Cursor);
+                }
+                let parse_method = self.generate_parse_raw(gen_ctx);
+                let parse = gen_ctx.wrap_async(quote!(#parse_method(&mut #reader_ident)));
+                write = quote!{
+                    // Only use the raw bytes if they still represent the value
+                    let #verbatim_ident = match #raw_source_ident {
+                        Some(raw) => {
+                            let mut #reader_ident = #cursor:: new(&raw);
+                            match #parse {
+                                Ok(
+                                    parsed
+                                ) if parsed == #elem_source_ident && #reader_ident.position() as usize == raw.len() => Some(
+                                    raw
+                                ),
+                                _ => None,
+                            }
+                        },
+                        None => None,
+                    };
+                    match #verbatim_ident {
+                        Some(raw) => {
+                            #dest_ident.extend(raw);
+                        },
+                        None => {
+                            #reserialize
+                        },
+                    }
+                };
+            },
+            _ => {
+                write = reserialize;
+            },
+        }
+        return quote!{
+            #dest_ident = vec ![];
+            {
+                let #elem_source_ident = #source_ident;
+                #write
+            }
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.element.0.mut_.borrow().rust_root.as_ref().unwrap().rust_type();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeCapture(pub(crate) Gc<NodeCapture_>);
+
+impl Into<Node> for NodeCapture {
+    fn into(self) -> Node {
+        return Node(Node_::Capture(self));
+    }
+}
+
+derive_forward_node_methods!(NodeCapture);
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeCaptureRawMut_ {
+    pub(crate) capture: Option<NodeCapture>,
+    pub(crate) rust: Option<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeCaptureRaw_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) mut_: GcCell<NodeCaptureRawMut_>,
+}
+
+impl NodeMethods for NodeCaptureRaw_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().capture.iter().map(|x| x.clone().into()).collect();
+    }
+
+    fn generate_read(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        // Set by the capture node
+        return quote!();
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().rust.dep();
+    }
+
+    fn generate_write(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        return quote!();
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        let bytes_type = rust_type_bytes();
+        return quote!(std:: option:: Option < #bytes_type >);
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeCaptureRaw(pub(crate) Gc<NodeCaptureRaw_>);
+
+impl Into<Node> for NodeCaptureRaw {
+    fn into(self) -> Node {
+        return Node(Node_::CaptureRaw(self));
+    }
+}
+
+derive_forward_node_methods!(NodeCaptureRaw);
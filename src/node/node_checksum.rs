@@ -0,0 +1,270 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        ToIdent,
+        generate_basic_read,
+        rename_ident,
+    },
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            ToDep,
+            Node_,
+        },
+        node_serial::NodeSerialSegment,
+    },
+    derive_forward_node_methods,
+    schema::GenerateContext,
+    scope::{
+        Scope,
+        Checksum,
+        Endian,
+    },
+};
+
+/// Segments are named after the node that owns them, see `Scope::seg`.
+fn segment_id(id: &str) -> String {
+    return format!("{}__serial_seg", id);
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeChecksumMut_ {
+    /// Nodes owning the covered segments, in the order they were added
+    pub(crate) covered: Vec<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeChecksum_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    #[unsafe_ignore_trace]
+    pub(crate) algorithm: Checksum,
+    pub(crate) endian: Endian,
+    pub(crate) mut_: GcCell<NodeChecksumMut_>,
+}
+
+impl NodeChecksum_ {
+    fn algorithm_type(&self) -> TokenStream {
+        match &self.algorithm {
+            Checksum::Crc32 => return quote!(inarybay_runtime::checksum::Crc32),
+            Checksum::Adler32 => return quote!(inarybay_runtime::checksum::Adler32),
+            Checksum::Crc16Ccitt => return quote!(inarybay_runtime::checksum::Crc16Ccitt),
+            Checksum::Internet => return quote!(inarybay_runtime::checksum::Internet),
+            Checksum::Xor => return quote!(inarybay_runtime::checksum::Xor),
+            Checksum::Custom(t) => return t.clone(),
+        }
+    }
+
+    fn value_type(&self) -> TokenStream {
+        let algorithm_type = self.algorithm_type();
+        return quote!(< #algorithm_type as inarybay_runtime:: checksum:: Checksum >:: Value);
+    }
+
+    fn endian_methods(&self) -> (TokenStream, TokenStream) {
+        match self.endian {
+            Endian::Big => return (quote!(from_be_bytes), quote!(to_be_bytes)),
+            Endian::Little => return (quote!(from_le_bytes), quote!(to_le_bytes)),
+        }
+    }
+
+    fn suffix_ident(&self, suffix: &str) -> Ident {
+        return format!("{}__{}", self.id, suffix).ident().unwrap();
+    }
+
+    pub(crate) fn covers(&self, id: &str) -> bool {
+        return self.mut_.borrow().covered.iter().any(|c| c.id() == id);
+    }
+
+    /// Declare the running checksum state, before any covered segments are read.
+    pub(crate) fn generate_read_init(&self) -> TokenStream {
+        let state_ident = self.suffix_ident("state");
+        let algorithm_type = self.algorithm_type();
+        return quote!{
+            let mut #state_ident =< #algorithm_type as std:: default:: Default >:: default();
+        };
+    }
+
+    /// Pass everything read by a covered node's read code through the checksum.
+    pub(crate) fn wrap_covered_read(&self, code: TokenStream) -> TokenStream {
+        let serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let state_ident = self.suffix_ident("state");
+        let observe_ident = self.suffix_ident("observe");
+        let reader_ident = self.suffix_ident("reader");
+        let code = rename_ident(code, serial_ident, &reader_ident);
+        return quote!{
+            let mut #observe_ident = inarybay_runtime:: ObserveRead:: new(
+                &mut * #serial_ident,
+                std:: mem:: take(&mut #state_ident)
+            );
+            let #reader_ident =& mut #observe_ident;
+            #code
+            //. .
+            #state_ident = #observe_ident.observer;
+        };
+    }
+
+    /// Compare the stored checksum with the computed one, after everything in the
+    /// scope has been read.
+    pub(crate) fn generate_read_verify(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let expected_ident = &self.id_ident;
+        let state_ident = self.suffix_ident("state");
+        let computed_ident = self.suffix_ident("computed");
+        let err =
+            gen_ctx.new_read_err(
+                &self.id,
+                "Checksum mismatch",
+                quote!(
+                    format!("Checksum mismatch, expected {:x} but computed {:x}", #expected_ident, #computed_ident)
+                ),
+            );
+        return quote!{
+            let #computed_ident = inarybay_runtime:: checksum:: Checksum:: finish(& #state_ident);
+            if #expected_ident != #computed_ident {
+                return Err(#err);
+            }
+        };
+    }
+}
+
+impl NodeMethods for NodeChecksum_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.serial.dep());
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let dest_ident = &self.id_ident;
+        let bytes_ident = self.suffix_ident("bytes");
+        let value_type = self.value_type();
+        let (from_bytes, _) = self.endian_methods();
+        let read =
+            generate_basic_read(
+                gen_ctx,
+                &self.id,
+                &bytes_ident,
+                &self.scope.0.serial_root.0.id_ident,
+                quote!(std:: mem:: size_of::< #value_type >()),
+            );
+        return quote!{
+            #read
+            //. .
+            #dest_ident =< #value_type >:: #from_bytes(#bytes_ident.try_into().unwrap());
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().covered.clone();
+    }
+
+    fn generate_write(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = &self.serial.0.id_ident;
+        let state_ident = self.suffix_ident("state");
+        let algorithm_type = self.algorithm_type();
+        let (_, to_bytes) = self.endian_methods();
+
+        // Update in serial order
+        let mut updates = vec![];
+        let covered = self.mut_.borrow().covered.iter().map(|c| segment_id(&c.id())).collect::<Vec<_>>();
+        for seg in &self.scope.0.serial_root.0.mut_.borrow().segments {
+            if !covered.contains(&seg.0.id) {
+                continue;
+            }
+            let seg_ident = &seg.0.id_ident;
+            updates.push(quote!{
+                inarybay_runtime:: checksum:: Checksum:: update(&mut #state_ident, & #seg_ident);
+            });
+        }
+        return quote!{
+            {
+                let mut #state_ident =< #algorithm_type as std:: default:: Default >:: default();
+                #(#updates) *
+                //. .
+                #source_ident = inarybay_runtime:: checksum:: Checksum:: finish(& #state_ident);
+            }
+            #dest_ident = #source_ident.#to_bytes().to_vec();
+        };
+    }
+
+    fn set_rust(&self, _rust: Node) {
+        panic!("Checksum {} is computed from the data it covers and can't be set from the rust side", self.id);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.value_type();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeChecksum(pub(crate) Gc<NodeChecksum_>);
+
+impl NodeChecksum {
+    /// Add a segment to the data covered by the checksum, for segments created after
+    /// the checksum.  The segment must be in the same scope as the checksum.  Ranges
+    /// can be covered via `Range::node`.
+    pub fn cover(&self, segment: impl Into<Node>) {
+        let segment = segment.into();
+        let id = segment.id();
+        if id == self.0.id {
+            panic!("Checksum {} can't cover itself", id);
+        }
+        let serial_root = &self.0.scope.0.serial_root;
+        let serial_root = serial_root.0.mut_.borrow();
+        let seg_id = segment_id(&id);
+        let Some(seg) = serial_root.segments.iter().find(|s| s.0.id == seg_id) else {
+            panic!(
+                "Checksum {} can only cover segments in its own scope, {} isn't a segment of that scope",
+                self.0.id,
+                id
+            );
+        };
+        match &segment.0 {
+            Node_::Checksum(_) => {
+                panic!("Checksum {} can't cover another checksum {}", self.0.id, id);
+            },
+            _ => { },
+        }
+        if self.0.covers(&id) {
+            return;
+        }
+        seg.0.mut_.borrow_mut().covered_by.push(self.clone().into());
+        self.0.mut_.borrow_mut().covered.push(segment);
+    }
+}
+
+impl Into<Node> for NodeChecksum {
+    fn into(self) -> Node {
+        return Node(Node_::Checksum(self));
+    }
+}
+
+derive_forward_node_methods!(NodeChecksum);
@@ -0,0 +1,167 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        ToIdent,
+        LateInit,
+    },
+    node::node::{
+        Node,
+        NodeMethods,
+        RedirectRef,
+        ToDep,
+        Node_,
+    },
+    derive_forward_node_methods,
+    schema::{
+        GenerateContext,
+        generate_nested_write,
+        generate_nested_read,
+    },
+    scope::{
+        Scope,
+        Codec,
+    },
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeDecodeMut_ {
+    pub(crate) serial: LateInit<RedirectRef<Node, Node>>,
+    pub(crate) rust: Option<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeDecode_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) element: Scope,
+    #[unsafe_ignore_trace]
+    pub(crate) codec: Codec,
+    pub(crate) max_size: usize,
+    pub(crate) mut_: GcCell<NodeDecodeMut_>,
+}
+
+impl NodeDecode_ {
+    fn codec_value(&self) -> TokenStream {
+        match &self.codec {
+            Codec::Zlib => return quote!(inarybay_runtime::codec::Zlib),
+            Codec::Deflate => return quote!(inarybay_runtime::codec::Deflate),
+            Codec::Gzip => return quote!(inarybay_runtime::codec::Gzip),
+            Codec::Lz4 => return quote!(inarybay_runtime::codec::Lz4),
+            Codec::Zstd => return quote!(inarybay_runtime::codec::Zstd),
+            Codec::Xor(key) => return quote!(inarybay_runtime::codec::Xor(&[#(#key,) *])),
+            Codec::Custom(t) => return t.clone(),
+        }
+    }
+}
+
+impl NodeMethods for NodeDecode_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().serial.dep();
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = self.mut_.borrow().serial.as_ref().unwrap().primary.id_ident();
+        let dest_ident = &self.id_ident;
+        let codec = self.codec_value();
+        let max_size = self.max_size;
+        let id = &self.id;
+        let elem_code = generate_nested_read(gen_ctx, &self.element);
+        let elem_dest_ident = self.element.get_rust_root().id_ident();
+        let inner_serial_ident = &self.element.0.serial_root.0.id_ident;
+        let reader_ident = "decode_read__".ident().unwrap();
+        let cursor;
+        if gen_ctx.async_ {
+            cursor = quote!(inarybay_runtime:: async_:: Cursor);
+        } else {
+            cursor = quote!(std:: io:: Cursor);
+        }
+        return quote!{
+            {
+                let decoded__ = inarybay_runtime:: codec:: Codec:: decode(
+                    &#codec,
+                    & #source_ident,
+                    #max_size
+                ).errorize_io(#id) ?;
+                let mut #reader_ident = #cursor:: new(decoded__);
+                {
+                    let #inner_serial_ident =& mut #reader_ident;
+                    //. .
+                    #elem_code
+                    //. .
+                    #dest_ident = #elem_dest_ident;
+                }
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().rust.dep();
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = self.mut_.borrow().serial.as_ref().unwrap().primary.id_ident();
+        let codec = self.codec_value();
+        let elem_code = generate_nested_write(gen_ctx, &self.element);
+        let elem_source_ident = self.element.get_rust_root().id_ident();
+        let elem_dest_ident = &self.element.0.serial_root.0.id_ident;
+        return quote!{
+            {
+                let mut #elem_dest_ident = std:: vec:: Vec::< u8 >:: new();
+                let #elem_source_ident = #source_ident;
+                #elem_code
+                //. .
+                #dest_ident = inarybay_runtime:: codec:: Codec:: encode(&#codec, & #elem_dest_ident) ?;
+            }
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.element.0.mut_.borrow().rust_root.as_ref().unwrap().rust_type();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeDecode(pub(crate) Gc<NodeDecode_>);
+
+impl Into<Node> for NodeDecode {
+    fn into(self) -> Node {
+        return Node(Node_::Decode(self));
+    }
+}
+
+derive_forward_node_methods!(NodeDecode);
@@ -0,0 +1,172 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            ToDep,
+        },
+        node_serial::NodeSerialSegment,
+    },
+    util::offset_ident,
+    derive_forward_node_methods,
+    schema::GenerateContext,
+    scope::Scope,
+};
+
+use super::node::Node_;
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeDelegateMut_ {
+    pub(crate) rust: Option<Node>,
+}
+
+/// A value of a hand-written type implementing `inarybay_runtime::traits::Delegate`
+/// (or `AsyncDelegate`), which reads and writes its own data.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeDelegate_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    #[unsafe_ignore_trace]
+    pub(crate) rust_type: TokenStream,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    pub(crate) mut_: GcCell<NodeDelegateMut_>,
+}
+
+impl NodeDelegate_ {
+    /// The stream offset is only known when writing if the preceding segments are
+    /// written first, which is the case in top level scopes.
+    fn write_offset_known(&self) -> bool {
+        return self.scope.0.mut_.borrow().generate_config.is_some();
+    }
+}
+
+impl NodeMethods for NodeDelegate_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.serial.dep());
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let dest_ident = &self.id_ident;
+        let source_ident = &self.serial.0.serial_root.0.id_ident;
+        let offset_ident = offset_ident();
+        let rust_type = &self.rust_type;
+        let method;
+        if gen_ctx.async_ {
+            method = quote!(< #rust_type as inarybay_runtime:: traits:: AsyncDelegate >:: read_async);
+        } else {
+            method = quote!(< #rust_type as inarybay_runtime:: traits:: Delegate >:: read);
+        }
+        let read = gen_ctx.wrap_read(&self.id, quote!(#method(&mut delegate_read__, #offset_ident)));
+        return quote!{
+            {
+                // Count what's read, since the implementation may not read to the end
+                let mut delegate_read__ = inarybay_runtime:: ObserveRead:: new(&mut * #source_ident, 0usize);
+                #dest_ident = #read;
+                #offset_ident += delegate_read__.observer;
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+        if self.write_offset_known() {
+            for seg in &self.serial.0.serial_root.0.mut_.borrow().segments {
+                if seg.0.id == self.serial.0.id {
+                    break;
+                }
+                if let Some(Node(Node_::Checksum(checksum))) = &seg.0.mut_.borrow().rust {
+                    if checksum.0.covers(&self.id) {
+                        panic!(
+                            "Delegate {} can't be covered by checksum {} which precedes it, since the delegate is written after the preceding segments to get its offset",
+                            self.id,
+                            checksum.0.id
+                        );
+                    }
+                }
+            }
+
+            // Written after the preceding segments, so the offset is at the segment start
+            out.extend(self.serial.0.serial_before.dep());
+        }
+        return out;
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = &self.serial.0.id_ident;
+        let rust_type = &self.rust_type;
+        let method;
+        if gen_ctx.async_ {
+            method = quote!(< #rust_type as inarybay_runtime:: traits:: AsyncDelegate >:: write_async);
+        } else {
+            method = quote!(< #rust_type as inarybay_runtime:: traits:: Delegate >:: write);
+        }
+        let offset;
+        if self.write_offset_known() {
+            let offset_ident = offset_ident();
+            offset = quote!(Some(#offset_ident));
+        } else {
+            offset = quote!(None);
+        }
+        let write = gen_ctx.wrap_write(quote!(#method(#source_ident, &mut #dest_ident, #offset)));
+        return quote!{
+            #dest_ident = vec ![];
+            #write;
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.rust_type.clone();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeDelegate(pub(crate) Gc<NodeDelegate_>);
+
+impl Into<Node> for NodeDelegate {
+    fn into(self) -> Node {
+        return Node(Node_::Delegate(self));
+    }
+}
+
+derive_forward_node_methods!(NodeDelegate);
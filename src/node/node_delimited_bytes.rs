@@ -25,6 +25,7 @@ use crate::{
     util::{
         generate_delimited_read,
         rust_type_bytes,
+        offset_ident,
     },
     derive_forward_node_methods,
     schema::GenerateContext,
@@ -46,12 +47,26 @@ pub(crate) struct NodeDelimitedBytes_ {
     pub(crate) id_ident: Ident,
     pub(crate) serial_before: Option<Node>,
     pub(crate) serial: NodeSerialSegment,
-    pub(crate) delim_len: usize,
+    /// The first delimiter is used when writing
     #[unsafe_ignore_trace]
-    pub(crate) delim_bytes: TokenStream,
+    pub(crate) delimiters: Vec<Vec<u8>>,
+    pub(crate) escape: Option<u8>,
+    pub(crate) strict: bool,
+    pub(crate) error_on_delimiter: bool,
     pub(crate) mut_: GcCell<NodeDelimitedBytesMut_>,
 }
 
+fn bytes_literal(bytes: &[u8]) -> TokenStream {
+    return quote!(&[#(#bytes,) *]);
+}
+
+impl NodeDelimitedBytes_ {
+    fn delimiters_literal(&self) -> TokenStream {
+        let delimiters = self.delimiters.iter().map(|d| bytes_literal(d)).collect::<Vec<_>>();
+        return quote!(&[#(#delimiters,) *]);
+    }
+}
+
 impl NodeMethods for NodeDelimitedBytes_ {
     fn gather_read_deps(&self) -> Vec<Node> {
         let mut out = vec![];
@@ -61,25 +76,67 @@ impl NodeMethods for NodeDelimitedBytes_ {
     }
 
     fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
-        return generate_delimited_read(
-            gen_ctx,
-            &self.id,
-            self.id_ident.clone(),
-            self.serial.0.serial_root.0.id_ident(),
-            &self.delim_bytes,
-        );
+        let source_ident = self.serial.0.serial_root.0.id_ident();
+        if self.delimiters.len() == 1 && self.escape.is_none() && !self.strict {
+            return generate_delimited_read(
+                gen_ctx,
+                &self.id,
+                self.id_ident.clone(),
+                source_ident,
+                &bytes_literal(&self.delimiters[0]),
+            );
+        }
+        let dest_ident = &self.id_ident;
+        let offset_ident = offset_ident();
+        let delimiters = self.delimiters_literal();
+        let escape;
+        match self.escape {
+            Some(e) => escape = quote!(Some(#e)),
+            None => escape = quote!(None),
+        }
+        let strict = self.strict;
+        let method;
+        if gen_ctx.async_ {
+            method = quote!(inarybay_runtime::async_::read_delimited_ext);
+        } else {
+            method = quote!(inarybay_runtime::read_delimited_ext);
+        }
+        let read = gen_ctx.wrap_read(&self.id, quote!(#method(#source_ident, #delimiters, #escape, #strict)));
+        return quote!{
+            #dest_ident = #read;
+            #offset_ident += #dest_ident.len();
+        };
     }
 
     fn gather_write_deps(&self) -> Vec<Node> {
         return self.mut_.borrow().rust.dep();
     }
 
-    fn generate_write(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
         let source_ident = &self.id_ident;
         let dest_ident = self.serial.0.id_ident();
-        let delim_len = &self.delim_len;
-        let delim_bytes = &self.delim_bytes;
+        let delim_len = self.delimiters[0].len();
+        let delim_bytes = bytes_literal(&self.delimiters[0]);
+        if let Some(escape) = self.escape {
+            let delimiters = self.delimiters_literal();
+            return quote!{
+                let mut #dest_ident = inarybay_runtime:: delimited:: escape(& #source_ident, #delimiters, #escape);
+                #dest_ident.extend_from_slice(#delim_bytes);
+            };
+        }
+        let mut check = quote!();
+        if self.error_on_delimiter {
+            let delimiters = self.delimiters_literal();
+            let err =
+                gen_ctx.new_write_err(&self.id, "Data contains delimiter", quote!("Data contains delimiter"));
+            check = quote!{
+                if inarybay_runtime:: delimited:: contains_delimiter(& #source_ident, #delimiters, #delim_bytes) {
+                    return Err(#err);
+                }
+            };
+        }
         return quote!{
+            #check
             let mut #dest_ident = #source_ident.clone();
             #dest_ident.resize(#dest_ident.len() + #delim_len, 0u8);
             #dest_ident[#source_ident.len()..].copy_from_slice(#delim_bytes);
@@ -12,6 +12,8 @@ use quote::quote;
 use crate::{
     util::{
         LateInit,
+        ToIdent,
+        generate_read_len,
     },
     node::{
         node::{
@@ -21,21 +23,159 @@ use crate::{
             ToDep,
             Node_,
         },
-        node_int::NodeInt,
         node_serial::NodeSerialSegment,
+        node_array_external::{
+            array_external_read_deps,
+            array_external_write_deps,
+            generate_array_external_write,
+        },
+        node_array_index::{
+            generate_array_index,
+            generate_element_read,
+        },
     },
     derive_forward_node_methods,
     schema::{
         GenerateContext,
-        generate_write,
-        generate_read,
+        generate_nested_write,
+        generate_nested_read,
     },
     scope::Scope,
 };
 
+#[derive(Trace, Finalize)]
+pub(crate) struct NodePreviousMut_ {
+    pub(crate) value: Option<Node>,
+}
+
+/// The value of a node in the previous array element, or an initial value for the
+/// first element.  This is a source in both directions: the array assigns it before
+/// each element is processed and updates it from the tracked value afterwards.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodePrevious_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    #[unsafe_ignore_trace]
+    pub(crate) initial: TokenStream,
+    pub(crate) mut_: GcCell<NodePreviousMut_>,
+}
+
+impl NodePrevious_ {
+    fn value(&self) -> Node {
+        return self.mut_.borrow().value.clone().expect(&format!("Value of previous element node {} was never set", self.id));
+    }
+
+    fn state_ident(&self) -> Ident {
+        return format!("{}__state", self.id).ident().unwrap();
+    }
+
+    /// Declares the state before the element loop
+    fn generate_init(&self) -> TokenStream {
+        let state_ident = self.state_ident();
+        let rust_type = self.rust_type();
+        let initial = &self.initial;
+        return quote!{
+            let mut #state_ident: #rust_type = #initial;
+        };
+    }
+
+    /// Exposes the state to the element code
+    fn generate_load(&self) -> TokenStream {
+        let id_ident = &self.id_ident;
+        let state_ident = self.state_ident();
+        return quote!{
+            let #id_ident = #state_ident;
+        };
+    }
+
+    /// Records the tracked value for the next element
+    fn generate_store(&self) -> TokenStream {
+        let state_ident = self.state_ident();
+        let value_ident = self.value().id_ident();
+        return quote!{
+            #state_ident = #value_ident;
+        };
+    }
+}
+
+impl NodeMethods for NodePrevious_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        return vec![];
+    }
+
+    fn generate_read(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        return quote!();
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return vec![];
+    }
+
+    fn generate_write(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        return quote!();
+    }
+
+    fn set_rust(&self, _rust: Node) {
+        // Known before the element is processed in both directions, so any number of
+        // consumers can use it directly
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.value().rust_type();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodePrevious(pub(crate) Gc<NodePrevious_>);
+
+impl NodePrevious {
+    /// Set the node whose value is carried to the next element.  `value` must be in
+    /// the element scope and its Rust type must be `Copy` (ex: an integer).
+    pub fn set_value(&self, value: impl Into<Node>) {
+        let value = value.into();
+        if value.scope().0.id != self.0.scope.0.id {
+            panic!("Value {} of previous element node {} is not in the element scope", value.id(), self.0.id);
+        }
+        let mut mut_ = self.0.mut_.borrow_mut();
+        if mut_.value.is_some() {
+            panic!("Value of previous element node {} already set", self.0.id);
+        }
+        mut_.value = Some(value.clone());
+        drop(mut_);
+
+        // Make sure the value is always produced, in both directions
+        let mut scope_mut = self.0.scope.0.mut_.borrow_mut();
+        scope_mut.rust_extra_roots.push(value.clone());
+        scope_mut.serial_extra_roots.push(value);
+    }
+}
+
+impl Into<Node> for NodePrevious {
+    fn into(self) -> Node {
+        return Node(Node_::Previous(self));
+    }
+}
+
+derive_forward_node_methods!(NodePrevious);
+
 #[derive(Trace, Finalize)]
 pub(crate) struct NodeDynamicArrayMut_ {
-    pub(crate) serial_len: LateInit<RedirectRef<NodeInt, Node>>,
+    pub(crate) serial_len: LateInit<RedirectRef<Node, Node>>,
+    pub(crate) previous: Vec<NodePrevious>,
     pub(crate) rust: Option<Node>,
 }
 
@@ -51,55 +191,103 @@ pub(crate) struct NodeDynamicArray_ {
     pub(crate) mut_: GcCell<NodeDynamicArrayMut_>,
 }
 
+impl NodeDynamicArray_ {
+    fn generate_previous(&self) -> (TokenStream, TokenStream, TokenStream) {
+        let mut init = vec![];
+        let mut load = vec![];
+        let mut store = vec![];
+        for previous in &self.mut_.borrow().previous {
+            init.push(previous.0.generate_init());
+            load.push(previous.0.generate_load());
+            store.push(previous.0.generate_store());
+        }
+        return (quote!(#(#init) *), quote!(#(#load) *), quote!(#(#store) *));
+    }
+}
+
 impl NodeMethods for NodeDynamicArray_ {
     fn gather_read_deps(&self) -> Vec<Node> {
         let mut out = vec![];
         out.extend(self.serial_before.dep());
         out.extend(self.mut_.borrow().serial_len.dep());
         out.extend(self.serial.dep());
+        out.extend(array_external_read_deps(&self.element));
         return out;
     }
 
     fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let (index_init, index_load) = generate_array_index(&self.element);
         let dest_ident = &self.id_ident;
-        let source_len_ident = self.mut_.borrow().serial_len.as_ref().unwrap().primary.0.id_ident.clone();
-        let elem_code = generate_read(gen_ctx, &self.element);
+        let source_len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
+        let source_len =
+            generate_read_len(gen_ctx, &self.id, &source_len.id_ident(), &source_len.rust_type().to_string());
+        let elem_code = generate_nested_read(gen_ctx, &self.element);
         let elem_dest_ident = self.element.get_rust_root().id_ident();
         let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
         let inner_serial_ident = &self.element.0.serial_root.0.id_ident;
+        let (prev_init, prev_load, prev_store) = self.generate_previous();
+        let index_ident = "elem_index__".ident().unwrap();
+        let elem_read = generate_element_read(gen_ctx, &index_ident, quote!{
+            let #inner_serial_ident =& mut * #outer_serial_ident;
+            #prev_load
+            //. .
+            #elem_code 
+            //. .
+            #prev_store
+            #dest_ident.push(#elem_dest_ident);
+        });
         return quote!{
             let mut #dest_ident = vec ![];
-            for _ in 0..#source_len_ident {
-                let #inner_serial_ident =& mut * #outer_serial_ident;
-                //. .
-                #elem_code 
-                //. .
-                #dest_ident.push(#elem_dest_ident);
+            #prev_init
+            #index_init
+            for #index_ident in 0..#source_len {
+                #index_load
+                #elem_read
             }
         };
     }
 
     fn gather_write_deps(&self) -> Vec<Node> {
-        return self.mut_.borrow().rust.dep();
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+        out.extend(array_external_write_deps(&self.element));
+        return out;
     }
 
     fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let (index_init, index_load) = generate_array_index(&self.element);
         let source_len_ident = self.id_ident();
-        let len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.0.clone();
+        let len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
         let dest_len_ident = len.id_ident();
-        let dest_len_type = &len.rust_type;
+        let dest_len_type = len.rust_type().to_string();
+        let len_err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Length too large for length field",
+                quote!(format!("Length {} too large for length field type {}", #source_len_ident.len(), #dest_len_type)),
+            );
         let dest_ident = self.serial.0.id_ident();
-        let elem_code = generate_write(gen_ctx, &self.element);
+        let elem_code = generate_nested_write(gen_ctx, &self.element);
         let elem_source_ident = self.element.get_rust_root().id_ident();
         let elem_dest_ident = &self.element.0.serial_root.0.id_ident;
+        let (prev_init, prev_load, prev_store) = self.generate_previous();
+        let (external_init, external_finish) = generate_array_external_write(gen_ctx, &self.element);
         return quote!{
-            #dest_len_ident = #source_len_ident.len() as #dest_len_type;
+            #dest_len_ident = #source_len_ident.len().try_into().map_err(|_| #len_err) ?;
             #dest_ident = vec ![];
+            #prev_init
+            #external_init
+            #index_init
             for #elem_source_ident in #source_len_ident {
+                #index_load
                 let #elem_dest_ident =& mut #dest_ident;
+                #prev_load
                 //. .
                 #elem_code
+                //. .
+                #prev_store
             }
+            #external_finish
         };
     }
 
@@ -134,6 +322,31 @@ impl NodeMethods for NodeDynamicArray_ {
 #[derive(Clone, Trace, Finalize)]
 pub struct NodeDynamicArray(pub(crate) Gc<NodeDynamicArray_>);
 
+impl NodeDynamicArray {
+    /// Create a node in the element scope holding a value from the previous element,
+    /// or `initial` for the first element.  Call `set_value` on the result to choose
+    /// the tracked value.  This can be used for delta encoding, run lengths, etc.
+    pub fn previous(&self, id: impl Into<String>, initial: TokenStream) -> NodePrevious {
+        let id = id.into();
+        let node = NodePrevious(Gc::new(NodePrevious_ {
+            scope: self.0.element.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            initial: initial,
+            mut_: GcCell::new(NodePreviousMut_ { value: None }),
+        }));
+
+        // Declared by the array
+        self.0.element.take_id(&id, None);
+        self.0.element.take_id(&format!("{}__state", id), None);
+        self.0.mut_.borrow_mut().previous.push(node.clone());
+
+        // Loaded and stored by the array around the element code
+        self.0.element.0.mut_.borrow_mut().has_external_deps = true;
+        return node;
+    }
+}
+
 impl Into<Node> for NodeDynamicArray {
     fn into(self) -> Node {
         return Node(Node_::DynamicArray(self));
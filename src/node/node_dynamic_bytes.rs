@@ -8,10 +8,7 @@ use proc_macro2::{
     TokenStream,
     Ident,
 };
-use quote::{
-    quote,
-    ToTokens,
-};
+use quote::quote;
 use crate::{
     node::{
         node::{
@@ -23,11 +20,11 @@ use crate::{
         node_serial::{
             NodeSerialSegment,
         },
-        node_int::NodeInt,
     },
     util::{
         LateInit,
         generate_basic_read,
+        generate_read_len,
         rust_type_bytes,
     },
     derive_forward_node_methods,
@@ -39,7 +36,7 @@ use super::node::Node_;
 
 #[derive(Trace, Finalize)]
 pub(crate) struct NodeDynamicBytesMut_ {
-    pub(crate) serial_len: LateInit<RedirectRef<NodeInt, Node>>,
+    pub(crate) serial_len: LateInit<RedirectRef<Node, Node>>,
     pub(crate) rust: Option<Node>,
 }
 
@@ -64,13 +61,13 @@ impl NodeMethods for NodeDynamicBytes_ {
     }
 
     fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
-        let len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.0.id_ident().to_token_stream();
+        let len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
         return generate_basic_read(
             gen_ctx,
             &self.id,
             &self.id_ident,
             &self.serial.0.serial_root.0.id_ident,
-            quote!(#len as usize),
+            generate_read_len(gen_ctx, &self.id, &len.id_ident(), &len.rust_type().to_string()),
         );
     }
 
@@ -78,14 +75,20 @@ impl NodeMethods for NodeDynamicBytes_ {
         return self.mut_.borrow().rust.dep();
     }
 
-    fn generate_write(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
         let source_ident = &self.id_ident;
         let dest_ident = &self.serial.0.id_ident;
-        let serial_len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.0.clone();
-        let dest_len_ident = &serial_len.id_ident;
-        let dest_len_type = &serial_len.rust_type;
+        let serial_len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
+        let dest_len_ident = serial_len.id_ident();
+        let dest_len_type = serial_len.rust_type().to_string();
+        let len_err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Length too large for length field",
+                quote!(format!("Length {} too large for length field type {}", #source_ident.len(), #dest_len_type)),
+            );
         return quote!{
-            #dest_len_ident = #source_ident.len() as #dest_len_type;
+            #dest_len_ident = #source_ident.len().try_into().map_err(|_| #len_err) ?;
             #dest_ident = #source_ident;
         };
     }
@@ -0,0 +1,324 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        LateInit,
+        ToIdent,
+        generate_read_len,
+    },
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            RedirectRef,
+            ToDep,
+            Node_,
+        },
+        node_serial::NodeSerialSegment,
+        node_array_external::{
+            array_external_read_deps,
+            array_external_write_deps,
+            generate_array_external_write,
+        },
+        node_array_index::{
+            generate_array_index,
+            generate_element_read,
+        },
+    },
+    derive_forward_node_methods,
+    schema::{
+        GenerateContext,
+        generate_nested_write,
+        generate_nested_read,
+    },
+    scope::{
+        Scope,
+        MapType,
+    },
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeMapKeyMut_ {
+    pub(crate) serial: LateInit<RedirectRef<Node, Node>>,
+}
+
+/// The rust end of a map element's key.  The key value is consumed by the map when
+/// reading and assigned from the map when writing.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeMapKey_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) mut_: GcCell<NodeMapKeyMut_>,
+}
+
+impl NodeMethods for NodeMapKey_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().serial.dep();
+    }
+
+    fn generate_read(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        return quote!();
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return vec![];
+    }
+
+    fn generate_write(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = self.mut_.borrow().serial.as_ref().unwrap().primary.id_ident();
+        return quote!{
+            #dest_ident = #source_ident;
+        };
+    }
+
+    fn set_rust(&self, _rust: Node) {
+        panic!("Map key {} can't be used as a value", self.id);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.mut_.borrow().serial.as_ref().unwrap().primary.rust_type();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub(crate) struct NodeMapKey(pub(crate) Gc<NodeMapKey_>);
+
+impl Into<Node> for NodeMapKey {
+    fn into(self) -> Node {
+        return Node(Node_::MapKey(self));
+    }
+}
+
+derive_forward_node_methods!(NodeMapKey);
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeDynamicMapMut_ {
+    pub(crate) serial_len: LateInit<RedirectRef<Node, Node>>,
+    pub(crate) key: Option<NodeMapKey>,
+    pub(crate) rust: Option<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeDynamicMap_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    pub(crate) element: Scope,
+    #[unsafe_ignore_trace]
+    pub(crate) map_type: MapType,
+    pub(crate) error_on_duplicate: bool,
+    pub(crate) mut_: GcCell<NodeDynamicMapMut_>,
+}
+
+impl NodeDynamicMap_ {
+    fn key(&self) -> NodeMapKey {
+        return self.mut_.borrow().key.clone().expect(&format!("Key of map {} was never set", self.id));
+    }
+
+    fn map_type(&self) -> TokenStream {
+        match self.map_type {
+            MapType::BTree => return quote!(std:: collections:: BTreeMap),
+            MapType::Hash => return quote!(std:: collections:: HashMap),
+        }
+    }
+}
+
+impl NodeMethods for NodeDynamicMap_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.mut_.borrow().serial_len.dep());
+        out.extend(self.serial.dep());
+        out.extend(array_external_read_deps(&self.element));
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let (index_init, index_load) = generate_array_index(&self.element);
+        let dest_ident = &self.id_ident;
+        let map_type = self.map_type();
+        let source_len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
+        let source_len =
+            generate_read_len(gen_ctx, &self.id, &source_len.id_ident(), &source_len.rust_type().to_string());
+        let key_ident = self.key().0.mut_.borrow().serial.as_ref().unwrap().primary.id_ident();
+        let elem_code = generate_nested_read(gen_ctx, &self.element);
+        let elem_dest_ident = self.element.get_rust_root().id_ident();
+        let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let inner_serial_ident = &self.element.0.serial_root.0.id_ident;
+        let mut check_duplicate = quote!();
+        if self.error_on_duplicate {
+            let err = gen_ctx.new_read_err(&self.id, "Duplicate key", quote!("Duplicate key".to_string()));
+            check_duplicate = quote!{
+                if #dest_ident.contains_key(& #key_ident) {
+                    return Err(#err);
+                }
+            };
+        }
+        let index_ident = "elem_index__".ident().unwrap();
+        let elem_read = generate_element_read(gen_ctx, &index_ident, quote!{
+            let #inner_serial_ident =& mut * #outer_serial_ident;
+            //. .
+            #elem_code 
+            //. .
+            #check_duplicate 
+            #dest_ident.insert(#key_ident, #elem_dest_ident);
+        });
+        return quote!{
+            let mut #dest_ident = #map_type:: new();
+            #index_init
+            for #index_ident in 0..#source_len {
+                #index_load
+                #elem_read
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+        out.extend(array_external_write_deps(&self.element));
+        return out;
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let (index_init, index_load) = generate_array_index(&self.element);
+        let source_ident = self.id_ident();
+        let len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
+        let dest_len_ident = len.id_ident();
+        let dest_len_type = len.rust_type().to_string();
+        let len_err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Length too large for length field",
+                quote!(format!("Length {} too large for length field type {}", #source_ident.len(), #dest_len_type)),
+            );
+        let dest_ident = self.serial.0.id_ident();
+        let key_source_ident = self.key().id_ident();
+        let elem_code = generate_nested_write(gen_ctx, &self.element);
+        let elem_source_ident = self.element.get_rust_root().id_ident();
+        let elem_dest_ident = &self.element.0.serial_root.0.id_ident;
+        let entries;
+        match self.map_type {
+            MapType::BTree => {
+                entries = quote!(#source_ident);
+            },
+            MapType::Hash => {
+                // Sort for deterministic output
+                entries = quote!({
+                    let mut entries__ = #source_ident.into_iter().collect:: < std:: vec:: Vec < _ >>();
+                    entries__.sort_by(|a, b| a.0.cmp(&b.0));
+                    entries__
+                });
+            },
+        }
+        let (external_init, external_finish) = generate_array_external_write(gen_ctx, &self.element);
+        return quote!{
+            #dest_len_ident = #source_ident.len().try_into().map_err(|_| #len_err) ?;
+            #dest_ident = vec ![];
+            #external_init
+            #index_init
+            for (#key_source_ident, #elem_source_ident) in #entries {
+                #index_load
+                let #elem_dest_ident =& mut #dest_ident;
+                //. .
+                #elem_code
+            }
+            #external_finish
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        let map_type = self.map_type();
+        let key_type = self.key().rust_type();
+        let elem_type = self.element.0.mut_.borrow().rust_root.as_ref().unwrap().rust_type();
+        return quote!(#map_type < #key_type, #elem_type >);
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeDynamicMap(pub(crate) Gc<NodeDynamicMap_>);
+
+impl NodeDynamicMap {
+    /// Set the map key.  `key` must be a value in the element scope, and can't be used
+    /// elsewhere.  The element scope's rust root becomes the map value.
+    pub fn key(&self, key: impl Into<Node>) {
+        let key = key.into();
+        if key.scope().0.id != self.0.element.0.id {
+            panic!("Key {} of map {} is not in the element scope", key.id(), self.0.id);
+        }
+        if self.0.mut_.borrow().key.is_some() {
+            panic!("Key of map {} already set", self.0.id);
+        }
+        let id = format!("{}__key", self.0.id);
+        let map_key = NodeMapKey(Gc::new(NodeMapKey_ {
+            scope: self.0.element.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            mut_: GcCell::new(NodeMapKeyMut_ { serial: None }),
+        }));
+        self.0.element.take_id(&id, None);
+        self.0.element.0.mut_.borrow_mut().rust_extra_roots.push(map_key.clone().into());
+        self.0.element.connect_value(&key, map_key.clone().into(), &mut map_key.0.mut_.borrow_mut().serial);
+        self.0.mut_.borrow_mut().key = Some(map_key);
+
+        // Taken out of and put into the element code by the map
+        self.0.element.0.mut_.borrow_mut().has_external_deps = true;
+    }
+}
+
+impl Into<Node> for NodeDynamicMap {
+    fn into(self) -> Node {
+        return Node(Node_::DynamicMap(self));
+    }
+}
+
+derive_forward_node_methods!(NodeDynamicMap);
@@ -24,12 +24,14 @@ use crate::{
         node_serial::NodeSerialSegment,
     },
     util::{
+        offset_ident,
         LateInit,
         ToIdent,
     },
     schema::{
-        generate_write,
-        generate_read,
+        generate_nested_write,
+        generate_nested_read,
+        scope_isolated,
         GenerateContext,
     },
     derive_forward_node_methods,
@@ -159,6 +161,41 @@ pub(crate) struct NodeEnum_ {
     pub(crate) mut_: GcCell<NodeEnumMut_>,
 }
 
+impl NodeEnum_ {
+    /// Values from outside the enum observed within variants
+    fn observed_externals(&self) -> Vec<Node> {
+        let mut out = vec![];
+        let mut_ = self.mut_.borrow();
+        for v in &mut_.variants {
+            out.extend(v.element.0.mut_.borrow().observed_externals.iter().cloned());
+        }
+        if let Some(default_v) = &mut_.default_variant {
+            out.extend(default_v.element.0.mut_.borrow().observed_externals.iter().cloned());
+        }
+        return out;
+    }
+
+    /// The enum can be read/written in its own function if no values are shared with
+    /// the enclosing scope
+    fn isolated(&self) -> bool {
+        let mut_ = self.mut_.borrow();
+        if !mut_.external_deps.is_empty() {
+            return false;
+        }
+        for v in &mut_.variants {
+            if !scope_isolated(&v.element) {
+                return false;
+            }
+        }
+        if let Some(default_v) = &mut_.default_variant {
+            if !scope_isolated(&default_v.element) {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
 impl NodeMethods for NodeEnum_ {
     fn gather_read_deps(&self) -> Vec<Node> {
         let mut out = vec![];
@@ -166,6 +203,7 @@ impl NodeMethods for NodeEnum_ {
         out.extend(self.mut_.borrow().serial_tag.dep());
         out.extend(self.serial.dep());
         out.extend(self.mut_.borrow().external_deps.values().cloned());
+        out.extend(self.observed_externals());
         return out;
     }
 
@@ -179,7 +217,7 @@ impl NodeMethods for NodeEnum_ {
             let var_ident = &v.var_name_ident;
             let rust_root = v.element.get_rust_root();
             let elem_ident = rust_root.id_ident();
-            let elem_code = generate_read(gen_ctx, &v.element);
+            let elem_code = generate_nested_read(gen_ctx, &v.element);
             let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
             let inner_serial_ident = &v.element.0.serial_root.0.id_ident;
             var_code.push(quote!{
@@ -197,7 +235,7 @@ impl NodeMethods for NodeEnum_ {
             let var_ident = &default_v.var_name_ident;
             let rust_root = default_v.element.get_rust_root();
             let elem_ident = rust_root.id_ident();
-            let elem_code = generate_read(gen_ctx, &default_v.element);
+            let elem_code = generate_nested_read(gen_ctx, &default_v.element);
             let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
             let inner_serial_ident = &default_v.element.0.serial_root.0.id_ident;
             default_code = quote!{
@@ -221,17 +259,65 @@ impl NodeMethods for NodeEnum_ {
                 }
             };
         }
-        return quote!{
+        let code = quote!{
             match #source_tag_ident {
                 #(#var_code) * 
                 //. .
                 #default_code
             };
         };
+        if !self.isolated() {
+            return code;
+        }
+        let offset_ident = offset_ident();
+        let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let tag_type = self.mut_.borrow().serial_tag.as_ref().unwrap().primary.rust_type();
+        let reader = gen_ctx.reader_bound(&self.scope.0.schema.0.borrow().reader_bounds);
+        let err_ident = gen_ctx.read_err_type();
+        let errors = gen_ctx.read_imports();
+        let method =
+            gen_ctx.add_fn(
+                false,
+                &self.type_name,
+                outer_serial_ident,
+                quote!(< R: #reader >),
+                vec![
+                    quote!(#outer_serial_ident:& mut R),
+                    quote!(offset__:& mut usize),
+                    quote!(depth__: usize),
+                    quote!(#source_tag_ident: #tag_type)
+                ],
+                quote!(Result < #type_ident, #err_ident >),
+                quote!{
+                    #errors 
+                    //. .
+                    let mut #offset_ident = * offset__;
+                    let mut #dest_ident: #type_ident;
+                    #code 
+                    //. .
+                    * offset__ = #offset_ident;
+                    return Ok(#dest_ident);
+                },
+            );
+        let read =
+            gen_ctx.wrap_async(
+                quote!(
+                    #method(&mut * #outer_serial_ident, &mut #offset_ident, depth__, #source_tag_ident.clone())
+                ),
+            );
+        return quote!{
+            #dest_ident = #read ?;
+        };
     }
 
     fn gather_write_deps(&self) -> Vec<Node> {
-        return self.mut_.borrow().rust.dep();
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+        for observed in self.observed_externals() {
+            // The observed value is assigned by its rust-side consumer
+            out.extend(observed.0.gather_write_deps());
+        }
+        return out;
     }
 
     fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
@@ -245,7 +331,7 @@ impl NodeMethods for NodeEnum_ {
             let variant_name = &v.var_name_ident;
             let elem_source_ident = v.element.get_rust_root().id_ident();
             let elem_dest_ident = &v.element.0.serial_root.0.id_ident;
-            let elem_code = generate_write(gen_ctx, &v.element);
+            let elem_code = generate_nested_write(gen_ctx, &v.element);
             var_code.push(quote!{
                 #enum_name:: #variant_name(#elem_source_ident) => {
                     let mut #elem_dest_ident = std:: vec:: Vec::< u8 >:: new();
@@ -262,7 +348,7 @@ impl NodeMethods for NodeEnum_ {
             let elem_dest_ident = &default_v.element.0.serial_root.0.id_ident;
             let tag_ident = &default_v.tag.id_ident();
             let tag_type_ident = default_v.tag.rust_type();
-            let write = generate_write(gen_ctx, &default_v.element);
+            let write = generate_nested_write(gen_ctx, &default_v.element);
             let elem_code = quote!{
                 let mut #tag_ident: #tag_type_ident;
                 #write 
@@ -278,13 +364,43 @@ impl NodeMethods for NodeEnum_ {
                 },
             });
         }
-        return quote!{
+        let code = quote!{
             #dest_ident = vec ![];
             //. .
             match #source_ident {
                 #(#var_code) *
             };
         };
+        if !self.isolated() {
+            return code;
+        }
+        let offset_ident = offset_ident();
+        let tag_type = self.mut_.borrow().serial_tag.as_ref().unwrap().primary.rust_type();
+        let imports = gen_ctx.write_imports();
+        let method =
+            gen_ctx.add_fn(
+                true,
+                &self.type_name,
+                &self.scope.0.serial_root.0.id_ident,
+                quote!(),
+                vec![quote!(offset__:& mut usize), quote!(#source_ident: #enum_name)],
+                quote!(std:: io:: Result < (#tag_type, std:: vec:: Vec < u8 >) >),
+                quote!{
+                    #imports 
+                    //. .
+                    let mut #offset_ident = * offset__;
+                    let mut #dest_ident: std:: vec:: Vec < u8 >;
+                    let mut #dest_tag_ident: #tag_type;
+                    #code 
+                    //. .
+                    * offset__ = #offset_ident;
+                    return Ok((#dest_tag_ident, #dest_ident));
+                },
+            );
+        let write = gen_ctx.wrap_write(quote!(#method(&mut #offset_ident, #source_ident)));
+        return quote!{
+            (#dest_tag_ident, #dest_ident) = #write;
+        };
     }
 
     fn set_rust(&self, rust: Node) {
@@ -0,0 +1,121 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    node::node::{
+        Node,
+        NodeMethods,
+        ToDep,
+        Node_,
+    },
+    derive_forward_node_methods,
+    schema::GenerateContext,
+    scope::Scope,
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeFanOutMut_ {
+    pub(crate) rust: Option<Node>,
+}
+
+/// A copy of a value for an additional consumer.  The copy is made when reading;
+/// when writing the consumer's value is compared with the value from the other
+/// consumers.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeFanOut_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    /// Observed, not connected
+    pub(crate) serial: Node,
+    pub(crate) mut_: GcCell<NodeFanOutMut_>,
+    // Computed
+    #[unsafe_ignore_trace]
+    pub(crate) rust_type: TokenStream,
+}
+
+impl NodeMethods for NodeFanOut_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        return vec![self.serial.clone()];
+    }
+
+    fn generate_read(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = self.serial.id_ident();
+        let dest_ident = &self.id_ident;
+        return quote!{
+            #dest_ident = #source_ident.clone();
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+
+        // The shared value is assigned by the other consumers
+        out.extend(self.serial.0.gather_write_deps());
+        return out;
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = self.serial.id_ident();
+        let serial_id = self.serial.id();
+        let err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Value doesn't match value from other uses",
+                quote!(format!("Value doesn't match value of {} from other uses", #serial_id)),
+            );
+        return quote!{
+            if #source_ident != #dest_ident {
+                return Err(#err);
+            }
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.rust_type.clone();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeFanOut(pub(crate) Gc<NodeFanOut_>);
+
+impl Into<Node> for NodeFanOut {
+    fn into(self) -> Node {
+        return Node(Node_::FanOut(self));
+    }
+}
+
+derive_forward_node_methods!(NodeFanOut);
@@ -0,0 +1,208 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        ToIdent,
+        offset_ident,
+    },
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            ToDep,
+            Node_,
+        },
+        node_serial::NodeSerialSegment,
+    },
+    derive_forward_node_methods,
+    schema::{
+        GenerateContext,
+        generate_nested_write,
+        generate_nested_read,
+    },
+    scope::{
+        Scope,
+        Framing,
+    },
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeFramedMut_ {
+    pub(crate) rust: Option<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeFramed_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    pub(crate) element: Scope,
+    #[unsafe_ignore_trace]
+    pub(crate) framing: Framing,
+    pub(crate) mut_: GcCell<NodeFramedMut_>,
+}
+
+impl NodeFramed_ {
+    /// Returns the delimiter, decode function, and encode function.
+    fn framing_parts(&self) -> (TokenStream, TokenStream, TokenStream) {
+        match self.framing {
+            Framing::Cobs => return (
+                quote!(inarybay_runtime:: framing:: COBS_DELIMITER),
+                quote!(inarybay_runtime:: framing:: cobs_decode),
+                quote!(inarybay_runtime:: framing:: cobs_encode),
+            ),
+            Framing::Slip => return (
+                quote!(inarybay_runtime:: framing:: SLIP_END),
+                quote!(inarybay_runtime:: framing:: slip_decode),
+                quote!(inarybay_runtime:: framing:: slip_encode),
+            ),
+            Framing::Hdlc => return (
+                quote!(inarybay_runtime:: framing:: HDLC_FLAG),
+                quote!(inarybay_runtime:: framing:: hdlc_decode),
+                quote!(inarybay_runtime:: framing:: hdlc_encode),
+            ),
+        }
+    }
+}
+
+impl NodeMethods for NodeFramed_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.serial.dep());
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let dest_ident = &self.id_ident;
+        let id = &self.id;
+        let (delimiter, decode, _) = self.framing_parts();
+        let elem_code = generate_nested_read(gen_ctx, &self.element);
+        let elem_dest_ident = self.element.get_rust_root().id_ident();
+        let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let inner_serial_ident = &self.element.0.serial_root.0.id_ident;
+        let offset_ident = offset_ident();
+        let reader_ident = "framed_read__".ident().unwrap();
+        let method;
+        let cursor;
+        if gen_ctx.async_ {
+            method = quote!(inarybay_runtime::async_::read_frame);
+            cursor = quote!(inarybay_runtime:: async_:: Cursor);
+        } else {
+            method = quote!(inarybay_runtime::read_frame);
+            cursor = quote!(std:: io:: Cursor);
+        }
+        let read = gen_ctx.wrap_read(id, quote!(#method(#outer_serial_ident, #delimiter)));
+        return quote!{
+            {
+                let frame__ = #read;
+                #offset_ident += frame__.len();
+                let decoded__ =
+                    #decode(
+                        inarybay_runtime:: framing:: trim_frame(&frame__, #delimiter)
+                    ).errorize_io(#id) ?;
+                let mut #reader_ident = #cursor:: new(decoded__);
+                {
+                    let #inner_serial_ident =& mut #reader_ident;
+                    //. .
+                    #elem_code
+                    //. .
+                    #dest_ident = #elem_dest_ident;
+                }
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().rust.dep();
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = &self.serial.0.id_ident;
+        let (delimiter, _, encode) = self.framing_parts();
+        let elem_code = generate_nested_write(gen_ctx, &self.element);
+        let elem_source_ident = self.element.get_rust_root().id_ident();
+        let elem_dest_ident = &self.element.0.serial_root.0.id_ident;
+        let mut open = quote!();
+        match self.framing {
+            Framing::Cobs => { },
+            Framing::Slip | Framing::Hdlc => {
+                // Empty frames are skipped when reading
+                let err = gen_ctx.new_write_err(&self.id, "Frame data is empty", quote!("Frame data is empty"));
+                open = quote!{
+                    if #elem_dest_ident.is_empty() {
+                        return Err(#err);
+                    }
+                };
+                if let Framing::Hdlc = self.framing {
+                    open = quote!{
+                        #open
+                        #dest_ident.push(#delimiter);
+                    };
+                }
+            },
+        }
+        return quote!{
+            #dest_ident = vec ![];
+            {
+                let mut #elem_dest_ident = std:: vec:: Vec::< u8 >:: new();
+                let #elem_source_ident = #source_ident;
+                #elem_code
+                //. .
+                #open
+                #dest_ident.extend(#encode(& #elem_dest_ident));
+                #dest_ident.push(#delimiter);
+            }
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.element.0.mut_.borrow().rust_root.as_ref().unwrap().rust_type();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeFramed(pub(crate) Gc<NodeFramed_>);
+
+impl Into<Node> for NodeFramed {
+    fn into(self) -> Node {
+        return Node(Node_::Framed(self));
+    }
+}
+
+derive_forward_node_methods!(NodeFramed);
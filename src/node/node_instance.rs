@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        offset_ident,
+        ToIdent,
+    },
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            ToDep,
+            Node_,
+        },
+        node_serial::NodeSerialSegment,
+    },
+    derive_forward_node_methods,
+    schema::{
+        GenerateContext,
+        TypeDef,
+    },
+    scope::Scope,
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeParamMut_ {
+    pub(crate) rust: Option<Node>,
+}
+
+/// A parameter of a type definition, passed to the definition's generated functions.
+/// When reading the argument is copied.  When writing, if the parameter has a
+/// rust-side consumer the consumer's value is compared with the argument, otherwise
+/// the argument is copied.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeParam_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    #[unsafe_ignore_trace]
+    pub(crate) rust_type: TokenStream,
+    pub(crate) mut_: GcCell<NodeParamMut_>,
+}
+
+impl NodeParam_ {
+    /// The generated function's argument
+    pub(crate) fn arg_ident(&self) -> Ident {
+        return format!("{}__arg", self.id).ident().unwrap();
+    }
+}
+
+impl NodeMethods for NodeParam_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        return vec![];
+    }
+
+    fn generate_read(&self, _gen_ctx: &GenerateContext) -> TokenStream {
+        let dest_ident = &self.id_ident;
+        let arg_ident = self.arg_ident();
+        return quote!{
+            #dest_ident = #arg_ident.clone();
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().rust.dep();
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let ident = &self.id_ident;
+        let arg_ident = self.arg_ident();
+        if self.mut_.borrow().rust.is_none() {
+            return quote!{
+                #ident = #arg_ident.clone();
+            };
+        }
+        let id = &self.id;
+        let err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Value doesn't match parameter",
+                quote!(format!("Value doesn't match value of parameter {}", #id)),
+            );
+        return quote!{
+            if #ident != #arg_ident {
+                return Err(#err);
+            }
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.rust_type.clone();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeParam(pub(crate) Gc<NodeParam_>);
+
+impl Into<Node> for NodeParam {
+    fn into(self) -> Node {
+        return Node(Node_::Param(self));
+    }
+}
+
+derive_forward_node_methods!(NodeParam);
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeInstanceMut_ {
+    /// Observed values bound to the definition's parameters, by parameter id
+    pub(crate) args: BTreeMap<String, Node>,
+    /// The value is a `Box`, for recursive definitions
+    pub(crate) boxed: bool,
+    pub(crate) rust: Option<Node>,
+}
+
+/// A use of a type definition, read and written by calling the definition's
+/// generated functions.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeInstance_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    pub(crate) def: TypeDef,
+    pub(crate) mut_: GcCell<NodeInstanceMut_>,
+}
+
+impl NodeInstance_ {
+    /// Bound values in parameter order
+    fn args(&self) -> Vec<Node> {
+        let mut out = vec![];
+        let mut_ = self.mut_.borrow();
+        for param in &self.def.0.mut_.borrow().params {
+            out.push(
+                mut_
+                    .args
+                    .get(&param.0.id)
+                    .expect(&format!("Parameter {} of instance {} was never bound", param.0.id, self.id))
+                    .clone(),
+            );
+        }
+        return out;
+    }
+
+    /// Bound values in this scope; values from enclosing scopes are handled by the
+    /// enclosing nodes
+    fn local_args(&self) -> Vec<Node> {
+        return self.args().into_iter().filter(|arg| arg.scope().0.id == self.scope.0.id).collect();
+    }
+
+    fn arg_values(&self) -> Vec<TokenStream> {
+        return self.args().iter().map(|arg| {
+            let ident = arg.id_ident();
+            quote!(#ident.clone())
+        }).collect();
+    }
+
+    /// Recursive async functions need an indirection
+    fn wrap_call(&self, gen_ctx: &GenerateContext, call: TokenStream) -> TokenStream {
+        if gen_ctx.async_ && self.def.recursive() {
+            return gen_ctx.wrap_async(quote!(std:: boxed:: Box:: pin(#call)));
+        }
+        return gen_ctx.wrap_async(call);
+    }
+}
+
+impl NodeMethods for NodeInstance_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.local_args());
+        out.extend(self.serial.dep());
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let dest_ident = &self.id_ident;
+        let source_ident = &self.scope.0.serial_root.0.id_ident;
+        let offset_ident = offset_ident();
+        let method = self.def.0.fn_ident(false, gen_ctx);
+        let args = self.arg_values();
+        let read =
+            self.wrap_call(gen_ctx, quote!(#method(#source_ident, &mut #offset_ident, depth__ + 1, #(#args,) *)));
+        if self.mut_.borrow().boxed {
+            return quote!{
+                #dest_ident = std:: boxed:: Box:: new(#read ?);
+            };
+        }
+        return quote!{
+            #dest_ident = #read ?;
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+        for arg in self.local_args() {
+            // The observed value is assigned by its rust-side consumer
+            out.extend(arg.0.gather_write_deps());
+        }
+        return out;
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = &self.serial.0.id_ident;
+        let offset_ident = offset_ident();
+        let method = self.def.0.fn_ident(true, gen_ctx);
+        let args = self.arg_values();
+        let source = if self.mut_.borrow().boxed {
+            quote!(* #source_ident)
+        } else {
+            quote!(#source_ident)
+        };
+        let write =
+            self.wrap_call(gen_ctx, quote!(#method(&mut #dest_ident, &mut instance_offset__, #(#args,) * #source)));
+        return quote!{
+            #dest_ident = vec ![];
+            {
+                // The segment is counted when it's written
+                let mut instance_offset__ = #offset_ident;
+                #write ?;
+            }
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        let rust_type = self.def.0.scope.get_rust_root().rust_type();
+        if self.mut_.borrow().boxed {
+            return quote!(std:: boxed:: Box < #rust_type >);
+        }
+        return rust_type;
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeInstance(pub(crate) Gc<NodeInstance_>);
+
+impl NodeInstance {
+    /// Bind a parameter of the type definition to a value in this scope or an
+    /// enclosing enum or array scope.  The value is only observed, so it needs a
+    /// rust-side consumer elsewhere.
+    pub fn bind(&self, param: &NodeParam, value: impl Into<Node>) {
+        let value = value.into();
+        if param.0.scope.0.id != self.0.def.0.scope.0.id {
+            panic!("Parameter {} is not a parameter of the definition of instance {}", param.0.id, self.0.id);
+        }
+        self.0.scope.observe(&value);
+        if self.0.mut_.borrow_mut().args.insert(param.0.id.clone(), value).is_some() {
+            panic!("Parameter {} of instance {} already bound", param.0.id, self.0.id);
+        }
+    }
+
+    /// Store the value in a `Box`.  This is required for instances of recursive
+    /// definitions (see `TypeDef::set_recursive`) that aren't in an array or other
+    /// container, like `Option<Box<T>>` or a field of type `Box<T>`.
+    pub fn boxed(&self) {
+        self.0.mut_.borrow_mut().boxed = true;
+    }
+}
+
+impl Into<Node> for NodeInstance {
+    fn into(self) -> Node {
+        return Node(Node_::Instance(self));
+    }
+}
+
+derive_forward_node_methods!(NodeInstance);
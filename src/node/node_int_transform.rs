@@ -0,0 +1,231 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+    Literal,
+};
+use quote::quote;
+use crate::{
+    util::{
+        LateInit,
+        ToIdent,
+    },
+    node::node::{
+        Node,
+        NodeMethods,
+        RedirectRef,
+        ToDep,
+        Node_,
+    },
+    derive_forward_node_methods,
+    schema::GenerateContext,
+    scope::{
+        Scope,
+        IntOp,
+    },
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeIntTransformMut_ {
+    pub(crate) serial: LateInit<RedirectRef<Node, Node>>,
+    pub(crate) rust: Option<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeIntTransform_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) op: IntOp,
+    pub(crate) mut_: GcCell<NodeIntTransformMut_>,
+    // Computed
+    #[unsafe_ignore_trace]
+    pub(crate) serial_type: TokenStream,
+    #[unsafe_ignore_trace]
+    pub(crate) rust_type: TokenStream,
+}
+
+/// The type of the transformed value for an input of integer type `serial_type`, wide
+/// enough that scaling a small length field doesn't overflow.
+pub(crate) fn widened_int_type(serial_type: &str) -> TokenStream {
+    match serial_type {
+        "u128" => return quote!(u128),
+        "i128" => return quote!(i128),
+        t if t.starts_with('i') => return quote!(i64),
+        _ => return quote!(u64),
+    }
+}
+
+/// Generates a statement checking that `source` can be divided by `divisor` without
+/// a remainder, followed by the division.
+fn generate_exact_div(dest_ident: &Ident, source: TokenStream, divisor: &Literal, err: TokenStream) -> TokenStream {
+    return quote!{
+        if #source % #divisor != 0 {
+            return Err(#err);
+        }
+        #dest_ident = #source / #divisor;
+    };
+}
+
+/// Generates a checked arithmetic operation (`checked_add`, etc).
+fn generate_checked(
+    dest_ident: &Ident,
+    source: TokenStream,
+    method: TokenStream,
+    arg: &Literal,
+    err: TokenStream,
+) -> TokenStream {
+    return quote!{
+        #dest_ident = #source.#method(#arg).ok_or_else(|| #err) ?;
+    };
+}
+
+impl NodeIntTransform_ {
+    /// Generates the inverse of the transform from the value to `value_ident`, in the
+    /// output type.
+    fn generate_write_inverse(&self, gen_ctx: &GenerateContext, value_ident: &Ident) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let source = quote!(#source_ident);
+        let err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Value can't be represented before transform",
+                quote!(format!("Value {} can't be represented before transform", #source_ident)),
+            );
+        match self.op {
+            IntOp::Add(v) => {
+                return generate_checked(value_ident, source, quote!(checked_sub), &Literal::u64_unsuffixed(v), err);
+            },
+            IntOp::Sub(v) => {
+                return generate_checked(value_ident, source, quote!(checked_add), &Literal::u64_unsuffixed(v), err);
+            },
+            IntOp::Mul(v) => {
+                let err =
+                    gen_ctx.new_write_err(
+                        &self.id,
+                        "Value not a multiple of multiplier",
+                        quote!(format!("Value {} is not a multiple of {}", #source_ident, #v)),
+                    );
+                return generate_exact_div(value_ident, source, &Literal::u64_unsuffixed(v), err);
+            },
+            IntOp::Div(v) => {
+                return generate_checked(value_ident, source, quote!(checked_mul), &Literal::u64_unsuffixed(v), err);
+            },
+        }
+    }
+}
+
+impl NodeMethods for NodeIntTransform_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().serial.dep();
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = self.mut_.borrow().serial.as_ref().unwrap().primary.id_ident();
+        let dest_ident = &self.id_ident;
+        let rust_type = &self.rust_type;
+        let source;
+        if self.serial_type.to_string() == rust_type.to_string() {
+            source = quote!(#source_ident);
+        } else {
+            source = quote!((#source_ident as #rust_type));
+        }
+        let err =
+            gen_ctx.new_read_err(
+                &self.id,
+                "Value out of range after transform",
+                quote!(format!("Value {} out of range after transform", #source_ident)),
+            );
+        match self.op {
+            IntOp::Add(v) => {
+                return generate_checked(dest_ident, source, quote!(checked_add), &Literal::u64_unsuffixed(v), err);
+            },
+            IntOp::Sub(v) => {
+                return generate_checked(dest_ident, source, quote!(checked_sub), &Literal::u64_unsuffixed(v), err);
+            },
+            IntOp::Mul(v) => {
+                return generate_checked(dest_ident, source, quote!(checked_mul), &Literal::u64_unsuffixed(v), err);
+            },
+            IntOp::Div(v) => {
+                let err =
+                    gen_ctx.new_read_err(
+                        &self.id,
+                        "Value not a multiple of divisor",
+                        quote!(format!("Value {} is not a multiple of {}", #source_ident, #v)),
+                    );
+                return generate_exact_div(dest_ident, source, &Literal::u64_unsuffixed(v), err);
+            },
+        }
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().rust.dep();
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let dest_ident = self.mut_.borrow().serial.as_ref().unwrap().primary.id_ident();
+        let rust_type = &self.rust_type;
+        let serial_type = &self.serial_type;
+        if serial_type.to_string() == rust_type.to_string() {
+            return self.generate_write_inverse(gen_ctx, &dest_ident);
+        }
+        let serial_type_name = serial_type.to_string();
+        let err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Value out of range for input type",
+                quote!(format!("Value {} out of range for input type {}", #source_ident, #serial_type_name)),
+            );
+        let value_ident = "transform_value__".ident().unwrap();
+        let inverse = self.generate_write_inverse(gen_ctx, &value_ident);
+        return quote!{
+            let #value_ident: #rust_type;
+            #inverse 
+            #dest_ident =< #serial_type >:: try_from(#value_ident).map_err(|_| #err) ?;
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.rust_type.clone();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeIntTransform(pub(crate) Gc<NodeIntTransform_>);
+
+impl Into<Node> for NodeIntTransform {
+    fn into(self) -> Node {
+        return Node(Node_::IntTransform(self));
+    }
+}
+
+derive_forward_node_methods!(NodeIntTransform);
@@ -207,15 +207,7 @@ impl NodeObj {
             mut_: GcCell::new(NodeObjFieldMut_ { serial: None }),
         }));
         self.0.mut_.borrow_mut().fields.push(rust.clone());
-        self
-            .0
-            .scope
-            .lift_connect(
-                &self.0.scope.get_ancestry_to(&serial),
-                &serial,
-                rust.clone().into(),
-                &mut rust.0.mut_.borrow_mut().serial,
-            );
+        self.0.scope.connect_value(&serial, rust.clone().into(), &mut rust.0.mut_.borrow_mut().serial);
     }
 }
 
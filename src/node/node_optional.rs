@@ -0,0 +1,312 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        LateInit,
+        ToIdent,
+    },
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            RedirectRef,
+            ToDep,
+            Node_,
+        },
+        node_serial::NodeSerialSegment,
+        node_array_external::{
+            array_external_read_deps,
+            array_external_write_deps,
+            generate_array_external_write,
+        },
+    },
+    derive_forward_node_methods,
+    schema::{
+        GenerateContext,
+        generate_nested_write,
+        generate_nested_read,
+    },
+    scope::Scope,
+};
+
+pub(crate) enum OptionalCondition {
+    /// Present if `serial_flag` is true/non-zero
+    Flag,
+    /// Present if the expression over the `condition_deps` idents is true
+    Expr(Box<dyn Fn(&Vec<Ident>) -> TokenStream>),
+    /// Present if there's remaining data
+    Eof,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeOptionalMut_ {
+    pub(crate) serial_flag: LateInit<RedirectRef<Node, Node>>,
+    pub(crate) rust: Option<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeOptional_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    pub(crate) element: Scope,
+    #[unsafe_ignore_trace]
+    pub(crate) condition: OptionalCondition,
+    /// Nodes observed by an `Expr` condition, in the same order as the idents passed
+    /// to it. Not connected (the value is only read) so they need a rust-side
+    /// consumer elsewhere.
+    pub(crate) condition_deps: Vec<Node>,
+    pub(crate) mut_: GcCell<NodeOptionalMut_>,
+}
+
+impl NodeOptional_ {
+    fn flag_is_bool(&self) -> bool {
+        return self.mut_.borrow().serial_flag.as_ref().unwrap().primary.rust_type().to_string() == "bool";
+    }
+
+    fn generate_condition(&self, condition: &Box<dyn Fn(&Vec<Ident>) -> TokenStream>) -> TokenStream {
+        let mut dep_idents = vec![];
+        for dep in &self.condition_deps {
+            dep_idents.push(dep.id_ident());
+        }
+        let condition = condition(&dep_idents);
+        return quote!((#condition));
+    }
+
+    fn present_ident(&self) -> Ident {
+        return format!("{}__present", self.id).ident().unwrap();
+    }
+
+    /// The directly preceding node, if it's also a trailing optional.
+    fn trailing_before(&self) -> Option<NodeOptional> {
+        let Some(Node(Node_::Optional(before))) = &self.serial_before else {
+            return None;
+        };
+        let OptionalCondition::Eof = before.0.condition else {
+            return None;
+        };
+        return Some(before.clone());
+    }
+}
+
+impl NodeMethods for NodeOptional_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.mut_.borrow().serial_flag.dep());
+        out.extend(self.condition_deps.iter().cloned());
+        out.extend(self.serial.dep());
+        out.extend(array_external_read_deps(&self.element));
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let dest_ident = &self.id_ident;
+        let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let present;
+        match &self.condition {
+            OptionalCondition::Flag => {
+                let source_flag_ident = self.mut_.borrow().serial_flag.as_ref().unwrap().primary.id_ident();
+                if self.flag_is_bool() {
+                    present = quote!(#source_flag_ident);
+                } else {
+                    present = quote!(#source_flag_ident != 0);
+                }
+            },
+            OptionalCondition::Expr(condition) => {
+                present = self.generate_condition(condition);
+            },
+            OptionalCondition::Eof => {
+                let method;
+                if gen_ctx.async_ {
+                    method = quote!(inarybay_runtime::async_::at_eof);
+                } else {
+                    method = quote!(inarybay_runtime::at_eof);
+                }
+                let at_eof = gen_ctx.wrap_read(&self.id, quote!(#method(#outer_serial_ident)));
+                present = quote!(! #at_eof);
+            },
+        }
+        let elem_code = generate_nested_read(gen_ctx, &self.element);
+        let elem_dest_ident = self.element.get_rust_root().id_ident();
+        let inner_serial_ident = &self.element.0.serial_root.0.id_ident;
+        return quote!{
+            if #present {
+                let #inner_serial_ident =& mut * #outer_serial_ident;
+                //. .
+                #elem_code
+                //. .
+                #dest_ident = Some(#elem_dest_ident);
+            }
+            else {
+                #dest_ident = None;
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+        for dep in &self.condition_deps {
+            if !dep.scope().has_rust_consumer(dep) {
+                panic!(
+                    "Conditional {} depends on {} which has no other rust-side consumer, so its value is unknown when writing; also use it elsewhere (ex: as an object field)",
+                    self.id,
+                    dep.id()
+                );
+            }
+            // The observed value is assigned by its rust-side consumer, before the node
+            // itself is written
+            out.extend(dep.0.gather_write_deps());
+        }
+        if let Some(before) = self.trailing_before() {
+            out.push(before.into());
+        }
+        out.extend(array_external_write_deps(&self.element));
+        return out;
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let source_ident = &self.id_ident;
+        let mut pre = quote!();
+        let present;
+        let absent;
+        match &self.condition {
+            OptionalCondition::Flag => {
+                let dest_flag_ident = self.mut_.borrow().serial_flag.as_ref().unwrap().primary.id_ident();
+                if self.flag_is_bool() {
+                    present = quote!(#dest_flag_ident = true;);
+                    absent = quote!(#dest_flag_ident = false;);
+                } else {
+                    present = quote!(#dest_flag_ident = 1;);
+                    absent = quote!(#dest_flag_ident = 0;);
+                }
+            },
+            OptionalCondition::Expr(condition) => {
+                let condition = self.generate_condition(condition);
+                let err_present =
+                    gen_ctx.new_write_err(
+                        &self.id,
+                        "Value is None but condition is true",
+                        quote!("Value is None but condition is true"),
+                    );
+                let err_absent =
+                    gen_ctx.new_write_err(
+                        &self.id,
+                        "Value is Some but condition is false",
+                        quote!("Value is Some but condition is false"),
+                    );
+                present = quote!{
+                    if !#condition {
+                        return Err(#err_absent);
+                    }
+                };
+                absent = quote!{
+                    if #condition {
+                        return Err(#err_present);
+                    }
+                };
+            },
+            OptionalCondition::Eof => {
+                // Data after a `None` would be read as the missing value, so make sure
+                // nothing follows it
+                let present_ident = self.present_ident();
+                pre = quote!{
+                    let #present_ident = #source_ident.is_some();
+                };
+                if let Some(before) = self.trailing_before() {
+                    let before_id = &before.0.id;
+                    let before_present_ident = before.0.present_ident();
+                    let err =
+                        gen_ctx.new_write_err(
+                            &self.id,
+                            "Value is Some but a preceding trailing value is None",
+                            quote!(format!("Value is Some but preceding trailing value {} is None", #before_id)),
+                        );
+                    present = quote!{
+                        if !#before_present_ident {
+                            return Err(#err);
+                        }
+                    };
+                } else {
+                    present = quote!();
+                }
+                absent = quote!();
+            },
+        }
+        let dest_ident = &self.serial.0.id_ident;
+        let elem_code = generate_nested_write(gen_ctx, &self.element);
+        let elem_source_ident = self.element.get_rust_root().id_ident();
+        let elem_dest_ident = &self.element.0.serial_root.0.id_ident;
+        let (external_init, external_finish) = generate_array_external_write(gen_ctx, &self.element);
+        return quote!{
+            #pre
+            #dest_ident = vec ![];
+            #external_init
+            //. .
+            match #source_ident {
+                Some(#elem_source_ident) => {
+                    let mut #elem_dest_ident = std:: vec:: Vec::< u8 >:: new();
+                    #elem_code
+                    //. .
+                    #dest_ident.extend(#elem_dest_ident);
+                    #present
+                },
+                None => {
+                    #absent
+                },
+            };
+            #external_finish
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        let elem_type_ident = &self.element.0.mut_.borrow().rust_root.as_ref().unwrap().rust_type();
+        return quote!(std:: option:: Option < #elem_type_ident >);
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeOptional(pub(crate) Gc<NodeOptional_>);
+
+impl Into<Node> for NodeOptional {
+    fn into(self) -> Node {
+        return Node(Node_::Optional(self));
+    }
+}
+
+derive_forward_node_methods!(NodeOptional);
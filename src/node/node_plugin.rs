@@ -0,0 +1,198 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        LateInit,
+        offset_ident,
+    },
+    node::{
+        node::{
+            Node,
+            RedirectRef,
+            NodeMethods,
+            ToDep,
+        },
+        node_serial::NodeSerialSegment,
+    },
+    derive_forward_node_methods,
+    schema::GenerateContext,
+    scope::Scope,
+};
+
+use super::node::Node_;
+
+/// Identifiers available to a plugin's read code.
+pub struct PluginRead<'a> {
+    pub gen_ctx: &'a GenerateContext,
+    /// The node id, for errors (see `GenerateContext::wrap_read`).
+    pub id: &'a str,
+    /// The reader (`&mut R` where `R: Read`, or `AsyncReadExt` when async), if the
+    /// node was created with a serial segment.
+    pub source: Option<Ident>,
+    /// The current stream offset (`usize`).  Code reading from `source` must add
+    /// the number of bytes read.
+    pub offset: Ident,
+    /// Values of the input nodes, in the order they were passed in.
+    pub inputs: Vec<Ident>,
+    /// Assign the resulting value to this.
+    pub dest: Ident,
+}
+
+/// Identifiers available to a plugin's write code.
+pub struct PluginWrite<'a> {
+    pub gen_ctx: &'a GenerateContext,
+    /// The node id, for errors (see `GenerateContext::new_write_err`).
+    pub id: &'a str,
+    /// The value to write.  Write code runs in dependency order rather than stream
+    /// order, so unlike `PluginRead` there's no offset.
+    pub source: Ident,
+    /// Assign a value to each of these, for the input nodes in the order they were
+    /// passed in.
+    pub inputs: Vec<Ident>,
+    /// A `Vec<u8>` to assign the serial segment's data to, if the node was created
+    /// with a serial segment.
+    pub dest: Option<Ident>,
+}
+
+/// A node kind defined outside this crate, added to a scope with `Scope::plugin`.
+/// The generated code is placed in the read/write functions once all of the node's
+/// dependencies are available.  Code using `?` must produce the errors of the
+/// surrounding function - use the `GenerateContext` helpers.
+pub trait Plugin {
+    /// The Rust type of the value the node produces when reading.
+    fn rust_type(&self) -> TokenStream;
+
+    /// If true the node reads/writes its own segment of the stream, ordered after
+    /// previously created segments in the scope.
+    fn has_serial(&self) -> bool {
+        return false;
+    }
+
+    /// Statements to produce the value from the inputs and/or serial segment.
+    fn generate_read(&self, ctx: &PluginRead) -> TokenStream;
+
+    /// Statements to produce the inputs and/or serial segment data from the value.
+    fn generate_write(&self, ctx: &PluginWrite) -> TokenStream;
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodePluginMut_ {
+    pub(crate) inputs: Vec<LateInit<RedirectRef<Node, Node>>>,
+    pub(crate) rust: Option<Node>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodePlugin_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    #[unsafe_ignore_trace]
+    pub(crate) rust_type: TokenStream,
+    #[unsafe_ignore_trace]
+    pub(crate) plugin: Box<dyn Plugin>,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: Option<NodeSerialSegment>,
+    pub(crate) mut_: GcCell<NodePluginMut_>,
+}
+
+impl NodePlugin_ {
+    fn input_idents(&self) -> Vec<Ident> {
+        let mut out = vec![];
+        for input in &self.mut_.borrow().inputs {
+            out.push(input.as_ref().unwrap().primary.id_ident());
+        }
+        return out;
+    }
+}
+
+impl NodeMethods for NodePlugin_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.serial.dep());
+        out.extend(self.mut_.borrow().inputs.dep());
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let code = self.plugin.generate_read(&PluginRead {
+            gen_ctx: gen_ctx,
+            id: &self.id,
+            source: self.serial.as_ref().map(|s| s.0.serial_root.0.id_ident.clone()),
+            offset: offset_ident(),
+            inputs: self.input_idents(),
+            dest: self.id_ident.clone(),
+        });
+        return quote!{
+            {
+                #code
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().rust.dep();
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let code = self.plugin.generate_write(&PluginWrite {
+            gen_ctx: gen_ctx,
+            id: &self.id,
+            source: self.id_ident.clone(),
+            inputs: self.input_idents(),
+            dest: self.serial.as_ref().map(|s| s.0.id_ident.clone()),
+        });
+        return quote!{
+            {
+                #code
+            }
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        return self.rust_type.clone();
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodePlugin(pub(crate) Gc<NodePlugin_>);
+
+impl Into<Node> for NodePlugin {
+    fn into(self) -> Node {
+        return Node(Node_::Plugin(self));
+    }
+}
+
+derive_forward_node_methods!(NodePlugin);
@@ -100,6 +100,9 @@ derive_forward_node_methods!(NodeSerial);
 #[derive(Trace, Finalize)]
 pub(crate) struct NodeSerialSegmentMut_ {
     pub(crate) rust: Option<Node>,
+    /// Checksums computed from the segment data, which must be done before the
+    /// segment is written out
+    pub(crate) covered_by: Vec<Node>,
 }
 
 #[derive(Trace, Finalize)]
@@ -129,6 +132,7 @@ impl NodeMethods for NodeSerialSegment_ {
         let mut out = vec![];
         out.extend(self.serial_before.dep());
         out.extend(self.mut_.borrow().rust.dep());
+        out.extend(self.mut_.borrow().covered_by.iter().cloned());
         return out;
     }
 
@@ -0,0 +1,258 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        LateInit,
+        offset_ident,
+        generate_read_len,
+        generate_write_len,
+        ToIdent,
+    },
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            RedirectRef,
+            ToDep,
+            Node_,
+        },
+        node_serial::NodeSerialSegment,
+        node_array_external::{
+            array_external_read_deps,
+            array_external_write_deps,
+            generate_array_external_write,
+        },
+        node_array_index::{
+            generate_array_index,
+            generate_element_read,
+        },
+    },
+    derive_forward_node_methods,
+    schema::{
+        GenerateContext,
+        generate_nested_write,
+        generate_nested_read,
+    },
+    scope::Scope,
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeStrideArrayMut_ {
+    pub(crate) serial_len: LateInit<RedirectRef<Node, Node>>,
+    pub(crate) rust: Option<Node>,
+}
+
+/// An array where each element occupies exactly `stride` bytes.
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeStrideArray_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    /// Observed, not connected
+    pub(crate) stride: Node,
+    pub(crate) preserve_tail: bool,
+    pub(crate) element: Scope,
+    pub(crate) mut_: GcCell<NodeStrideArrayMut_>,
+}
+
+impl NodeMethods for NodeStrideArray_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.mut_.borrow().serial_len.dep());
+        out.push(self.stride.clone());
+        out.extend(self.serial.dep());
+        out.extend(array_external_read_deps(&self.element));
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let (index_init, index_load) = generate_array_index(&self.element);
+        let dest_ident = &self.id_ident;
+        let source_len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
+        let source_len =
+            generate_read_len(gen_ctx, &self.id, &source_len.id_ident(), &source_len.rust_type().to_string());
+        let stride_type = self.stride.rust_type().to_string();
+        let stride = generate_read_len(gen_ctx, &self.id, &self.stride.id_ident(), &stride_type);
+        let elem_code = generate_nested_read(gen_ctx, &self.element);
+        let elem_dest_ident = self.element.get_rust_root().id_ident();
+        let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let inner_serial_ident = &self.element.0.serial_root.0.id_ident;
+        let offset_ident = offset_ident();
+        let method;
+        let cursor;
+        if gen_ctx.async_ {
+            method = quote!(inarybay_runtime::async_::read);
+            cursor = quote!(inarybay_runtime:: async_:: Cursor);
+        } else {
+            method = quote!(inarybay_runtime::read);
+            cursor = quote!(std:: io:: Cursor);
+        }
+        let read = gen_ctx.wrap_read(&self.id, quote!(#method(#outer_serial_ident, stride__)));
+        let push;
+        if self.preserve_tail {
+            push = quote!{
+                let tail__ = stride_read__.into_inner()[consumed__..].to_vec();
+                #dest_ident.push((stride_elem__, tail__));
+            };
+        } else {
+            push = quote!{
+                #dest_ident.push(stride_elem__);
+            };
+        }
+        let index_ident = "elem_index__".ident().unwrap();
+        let elem_read = generate_element_read(gen_ctx, &index_ident, quote!{
+            let stride_data__;
+            if stride__ == 0 {
+                stride_data__ = std:: vec:: Vec::< u8 >:: new();
+            }
+            else {
+                stride_data__ = #read;
+            }
+            let mut stride_read__ = #cursor:: new(stride_data__);
+            let stride_elem__;
+            {
+                let #inner_serial_ident =& mut stride_read__;
+                //. .
+                #elem_code 
+                //. .
+                stride_elem__ = #elem_dest_ident;
+            }
+            let consumed__ = stride_read__.position() as usize;
+            // Element reads are already counted
+            #offset_ident += stride__ - consumed__;
+            #push
+        });
+        return quote!{
+            let mut #dest_ident = vec ![];
+            let stride__ = #stride;
+            #index_init
+            for #index_ident in 0..#source_len {
+                #index_load
+                #elem_read
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.mut_.borrow().rust.dep());
+
+        // The stride is assigned by its rust-side consumer
+        out.extend(self.stride.0.gather_write_deps());
+        out.extend(array_external_write_deps(&self.element));
+        return out;
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let (index_init, index_load) = generate_array_index(&self.element);
+        let source_len_ident = self.id_ident();
+        let len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
+        let dest_len_ident = len.id_ident();
+        let dest_len_type = len.rust_type().to_string();
+        let stride_type = self.stride.rust_type().to_string();
+        let stride = generate_write_len(gen_ctx, &self.id, &self.stride.id_ident(), &stride_type);
+        let len_err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Length too large for length field",
+                quote!(format!("Length {} too large for length field type {}", #source_len_ident.len(), #dest_len_type)),
+            );
+        let stride_err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Element larger than stride",
+                quote!(format!("Element size {} larger than stride {}", elem_data__.len(), stride__)),
+            );
+        let dest_ident = self.serial.0.id_ident();
+        let elem_code = generate_nested_write(gen_ctx, &self.element);
+        let elem_source_ident = self.element.get_rust_root().id_ident();
+        let elem_dest_ident = &self.element.0.serial_root.0.id_ident;
+        let elem_pattern;
+        let add_tail;
+        if self.preserve_tail {
+            elem_pattern = quote!((#elem_source_ident, tail__));
+            add_tail = quote!(elem_data__.extend(tail__););
+        } else {
+            elem_pattern = quote!(#elem_source_ident);
+            add_tail = quote!();
+        }
+        let (external_init, external_finish) = generate_array_external_write(gen_ctx, &self.element);
+        return quote!{
+            #dest_len_ident = #source_len_ident.len().try_into().map_err(|_| #len_err) ?;
+            #dest_ident = vec ![];
+            let stride__ = #stride;
+            #external_init
+            #index_init
+            for #elem_pattern in #source_len_ident {
+                #index_load
+                let mut elem_data__ = std:: vec:: Vec::< u8 >:: new();
+                {
+                    let #elem_dest_ident =& mut elem_data__;
+                    //. .
+                    #elem_code
+                }
+                #add_tail
+                if elem_data__.len() > stride__ {
+                    return Err(#stride_err);
+                }
+                elem_data__.resize(stride__, 0u8);
+                #dest_ident.extend(elem_data__);
+            }
+            #external_finish
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        let elem_type_ident = &self.element.0.mut_.borrow().rust_root.as_ref().unwrap().rust_type();
+        if self.preserve_tail {
+            return quote!(std:: vec:: Vec < (#elem_type_ident, std:: vec:: Vec < u8 >) >);
+        }
+        return quote!(std:: vec:: Vec < #elem_type_ident >);
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeStrideArray(pub(crate) Gc<NodeStrideArray_>);
+
+impl Into<Node> for NodeStrideArray {
+    fn into(self) -> Node {
+        return Node(Node_::StrideArray(self));
+    }
+}
+
+derive_forward_node_methods!(NodeStrideArray);
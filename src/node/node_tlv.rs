@@ -0,0 +1,522 @@
+use gc::{
+    Finalize,
+    Trace,
+    Gc,
+    GcCell,
+};
+use proc_macro2::{
+    TokenStream,
+    Ident,
+};
+use quote::quote;
+use crate::{
+    util::{
+        LateInit,
+        ToIdent,
+        generate_basic_read,
+        generate_read_len,
+        rust_type_bytes,
+    },
+    node::{
+        node::{
+            Node,
+            NodeMethods,
+            RedirectRef,
+            ToDep,
+            Node_,
+        },
+        node_serial::NodeSerialSegment,
+    },
+    derive_forward_node_methods,
+    schema::{
+        GenerateContext,
+        generate_nested_write,
+        generate_nested_read,
+    },
+    scope::{
+        Scope,
+        Endian,
+    },
+};
+
+#[derive(Trace, Finalize)]
+pub(crate) enum TlvEnd_ {
+    Eof,
+    Len,
+    Terminator(#[unsafe_ignore_trace] TokenStream),
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct TlvVariant {
+    pub(crate) var_name: String,
+    #[unsafe_ignore_trace]
+    pub(crate) var_name_ident: Ident,
+    #[unsafe_ignore_trace]
+    pub(crate) tag: TokenStream,
+    pub(crate) element: Scope,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeTlvMut_ {
+    pub(crate) serial_len: LateInit<RedirectRef<Node, Node>>,
+    pub(crate) variants: Vec<TlvVariant>,
+    pub(crate) rust: Option<Node>,
+    #[unsafe_ignore_trace]
+    pub(crate) type_attrs: Vec<TokenStream>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct NodeTlv_ {
+    pub(crate) scope: Scope,
+    pub(crate) id: String,
+    #[unsafe_ignore_trace]
+    pub(crate) id_ident: Ident,
+    pub(crate) type_name: String,
+    #[unsafe_ignore_trace]
+    pub(crate) type_name_ident: Ident,
+    pub(crate) serial_before: Option<Node>,
+    pub(crate) serial: NodeSerialSegment,
+    pub(crate) type_bytes: usize,
+    pub(crate) len_bytes: usize,
+    pub(crate) endian: Endian,
+    pub(crate) length_first: bool,
+    pub(crate) len_includes_type: bool,
+    pub(crate) end: TlvEnd_,
+    pub(crate) mut_: GcCell<NodeTlvMut_>,
+}
+
+pub(crate) fn tlv_int_type(bytes: usize) -> Option<TokenStream> {
+    match bytes {
+        1 => return Some(quote!(u8)),
+        2 => return Some(quote!(u16)),
+        4 => return Some(quote!(u32)),
+        8 => return Some(quote!(u64)),
+        _ => return None,
+    }
+}
+
+impl NodeTlv_ {
+    pub(crate) fn tag_type(&self) -> TokenStream {
+        return tlv_int_type(self.type_bytes).unwrap();
+    }
+
+    fn len_type(&self) -> TokenStream {
+        return tlv_int_type(self.len_bytes).unwrap();
+    }
+
+    fn endian_methods(&self) -> (TokenStream, TokenStream) {
+        match self.endian {
+            Endian::Big => return (quote!(from_be_bytes), quote!(to_be_bytes)),
+            Endian::Little => return (quote!(from_le_bytes), quote!(to_le_bytes)),
+        }
+    }
+
+    /// Generates a read of a type or length field into `dest_ident`.
+    fn generate_read_int(
+        &self,
+        gen_ctx: &GenerateContext,
+        dest_ident: &Ident,
+        source_ident: &Ident,
+        bytes: usize,
+        int_type: &TokenStream,
+    ) -> TokenStream {
+        let (from_bytes, _) = self.endian_methods();
+        let bytes_ident = format!("{}_bytes", dest_ident).ident().unwrap();
+        let read = generate_basic_read(gen_ctx, &self.id, &bytes_ident, source_ident, quote!(#bytes));
+        return quote!{
+            #read
+            let #dest_ident =< #int_type >:: #from_bytes(#bytes_ident.try_into().unwrap());
+        };
+    }
+
+    /// Generates the reads of the type and length of an entry, followed by the value.
+    fn generate_read_entry(&self, gen_ctx: &GenerateContext, source_ident: &Ident) -> (TokenStream, TokenStream) {
+        let tag_type = self.tag_type();
+        let len_type = self.len_type();
+        let type_ident = "tlv_type__".ident().unwrap();
+        let len_ident = "tlv_len__".ident().unwrap();
+        let read_type = self.generate_read_int(gen_ctx, &type_ident, source_ident, self.type_bytes, &tag_type);
+        let read_len = self.generate_read_int(gen_ctx, &len_ident, source_ident, self.len_bytes, &len_type);
+        let header;
+        if self.length_first {
+            header = quote!{
+                #read_len
+                #read_type
+            };
+        } else {
+            header = quote!{
+                #read_type
+                #read_len
+            };
+        }
+        let len_usize = generate_read_len(gen_ctx, &self.id, &len_ident, &len_type.to_string());
+        let value_len;
+        if self.len_includes_type {
+            let type_bytes = self.type_bytes;
+            let err =
+                gen_ctx.new_read_err(
+                    &self.id,
+                    "Entry length smaller than type",
+                    quote!(format!("Entry length {} smaller than type size {}", #len_ident, #type_bytes)),
+                );
+            value_len = quote!((#len_usize).checked_sub(#type_bytes).ok_or_else(|| #err) ?);
+        } else {
+            value_len = len_usize;
+        }
+        let value_bytes_ident = "tlv_value__".ident().unwrap();
+        let read_value =
+            generate_basic_read(
+                gen_ctx,
+                &self.id,
+                &"tlv_value_read__".ident().unwrap(),
+                source_ident,
+                quote!(tlv_value_len__),
+            );
+        let value = quote!{
+            let tlv_value_len__ = #value_len;
+            let #value_bytes_ident;
+            if tlv_value_len__ == 0 {
+                #value_bytes_ident = std:: vec:: Vec::< u8 >:: new();
+            }
+            else {
+                #read_value
+                #value_bytes_ident = tlv_value_read__;
+            }
+        };
+        return (header, value);
+    }
+}
+
+impl NodeMethods for NodeTlv_ {
+    fn gather_read_deps(&self) -> Vec<Node> {
+        let mut out = vec![];
+        out.extend(self.serial_before.dep());
+        out.extend(self.mut_.borrow().serial_len.dep());
+        out.extend(self.serial.dep());
+        return out;
+    }
+
+    fn generate_read(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let type_name_ident = &self.type_name_ident;
+        let dest_ident = &self.id_ident;
+        let outer_serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let reader_ident = "tlv_read__".ident().unwrap();
+        let cursor;
+        let at_eof_method;
+        if gen_ctx.async_ {
+            cursor = quote!(inarybay_runtime:: async_:: Cursor);
+            at_eof_method = quote!(inarybay_runtime::async_::at_eof);
+        } else {
+            cursor = quote!(std:: io:: Cursor);
+            at_eof_method = quote!(inarybay_runtime::at_eof);
+        }
+        let (header, value) = self.generate_read_entry(gen_ctx, &reader_ident);
+        let mut var_code = vec![];
+        for v in &self.mut_.borrow().variants {
+            let tag = &v.tag;
+            let var_ident = &v.var_name_ident;
+            let elem_dest_ident = v.element.get_rust_root().id_ident();
+            let elem_code = generate_nested_read(gen_ctx, &v.element);
+            let inner_serial_ident = &v.element.0.serial_root.0.id_ident;
+            let err =
+                gen_ctx.new_read_err(
+                    &self.id,
+                    "Entry has unread data after the value",
+                    quote!(
+                        format!(
+                            "Entry with type {} has {} bytes of unread data after the value",
+                            tlv_type__,
+                            tlv_value_read__.get_ref().len() - tlv_value_read__.position() as usize
+                        )
+                    ),
+                );
+            var_code.push(quote!{
+                #tag => {
+                    let mut tlv_value_read__ = #cursor:: new(tlv_value__);
+                    let #inner_serial_ident =& mut tlv_value_read__;
+                    //. .
+                    #elem_code
+                    //. .
+                    if tlv_value_read__.position() as usize != tlv_value_read__.get_ref().len() {
+                        return Err(#err);
+                    }
+                    #dest_ident.push(#type_name_ident:: #var_ident(#elem_dest_ident));
+                },
+            });
+        }
+        let at_eof = gen_ctx.wrap_read(&self.id, quote!(#at_eof_method(#reader_ident)));
+        let setup;
+        let check_end;
+        let check_terminator;
+        match &self.end {
+            TlvEnd_::Eof => {
+                setup = quote!(let #reader_ident =& mut * #outer_serial_ident;);
+                check_end = quote!{
+                    if #at_eof {
+                        break;
+                    }
+                };
+                check_terminator = quote!();
+            },
+            TlvEnd_::Len => {
+                let source_len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
+                let source_len_ident = source_len.id_ident();
+                let source_len_usize =
+                    generate_read_len(gen_ctx, &self.id, &source_len_ident, &source_len.rust_type().to_string());
+                let method;
+                if gen_ctx.async_ {
+                    method = quote!(inarybay_runtime::async_::read);
+                } else {
+                    method = quote!(inarybay_runtime::read);
+                }
+
+                // Not added to the offset, the entry reads do that
+                let read =
+                    gen_ctx.wrap_read(&self.id, quote!(#method(#outer_serial_ident, #source_len_usize)));
+                setup = quote!{
+                    let tlv_data__;
+                    if #source_len_ident == 0 {
+                        tlv_data__ = std:: vec:: Vec::< u8 >:: new();
+                    }
+                    else {
+                        tlv_data__ = #read;
+                    }
+                    let mut tlv_data_read__ = #cursor:: new(tlv_data__);
+                    let #reader_ident =& mut tlv_data_read__;
+                };
+                check_end = quote!{
+                    if #at_eof {
+                        break;
+                    }
+                };
+                check_terminator = quote!();
+            },
+            TlvEnd_::Terminator(tag) => {
+                setup = quote!(let #reader_ident =& mut * #outer_serial_ident;);
+                check_end = quote!();
+                check_terminator = quote!{
+                    if tlv_type__ == #tag {
+                        break;
+                    }
+                };
+            },
+        }
+        return quote!{
+            let mut #dest_ident = vec ![];
+            {
+                #setup
+                loop {
+                    #check_end
+                    #header
+                    #value
+                    #check_terminator
+                    match tlv_type__ {
+                        #(#var_code) *
+                        //. .
+                        _ => {
+                            #dest_ident.push(#type_name_ident:: Unknown(tlv_type__, tlv_value__));
+                        }
+                    }
+                }
+            }
+        };
+    }
+
+    fn gather_write_deps(&self) -> Vec<Node> {
+        return self.mut_.borrow().rust.dep();
+    }
+
+    fn generate_write(&self, gen_ctx: &GenerateContext) -> TokenStream {
+        let type_name_ident = &self.type_name_ident;
+        let source_ident = &self.id_ident;
+        let dest_ident = &self.serial.0.id_ident;
+        let tag_type = self.tag_type();
+        let len_type = self.len_type();
+        let len_type_str = len_type.to_string();
+        let (_, to_bytes) = self.endian_methods();
+        let mut var_code = vec![];
+        for v in &self.mut_.borrow().variants {
+            let tag = &v.tag;
+            let var_ident = &v.var_name_ident;
+            let elem_source_ident = v.element.get_rust_root().id_ident();
+            let elem_dest_ident = &v.element.0.serial_root.0.id_ident;
+            let elem_code = generate_nested_write(gen_ctx, &v.element);
+            var_code.push(quote!{
+                #type_name_ident:: #var_ident(#elem_source_ident) => {
+                    let mut #elem_dest_ident = std:: vec:: Vec::< u8 >:: new();
+                    #elem_code
+                    //. .
+                    tlv_type__ = #tag;
+                    tlv_value__ = #elem_dest_ident;
+                },
+            });
+        }
+        let len_err =
+            gen_ctx.new_write_err(
+                &self.id,
+                "Entry too large for length field",
+                quote!(format!("Entry length {} too large for length field type {}", tlv_len__, #len_type_str)),
+            );
+        let len;
+        if self.len_includes_type {
+            let type_bytes = self.type_bytes;
+            len = quote!(tlv_value__.len() + #type_bytes);
+        } else {
+            len = quote!(tlv_value__.len());
+        }
+        let header;
+        if self.length_first {
+            header = quote!{
+                #dest_ident.extend(tlv_len__.#to_bytes());
+                #dest_ident.extend(tlv_type__.#to_bytes());
+            };
+        } else {
+            header = quote!{
+                #dest_ident.extend(tlv_type__.#to_bytes());
+                #dest_ident.extend(tlv_len__.#to_bytes());
+            };
+        }
+        let write_entry = quote!{
+            let tlv_len__ = #len;
+            let tlv_len__: #len_type = tlv_len__.try_into().map_err(|_| #len_err) ?;
+            #header
+            #dest_ident.extend(tlv_value__);
+        };
+        let finish;
+        match &self.end {
+            TlvEnd_::Eof => {
+                finish = quote!();
+            },
+            TlvEnd_::Len => {
+                let len = self.mut_.borrow().serial_len.as_ref().unwrap().primary.clone();
+                let dest_len_ident = len.id_ident();
+                let dest_len_type = len.rust_type().to_string();
+                let err =
+                    gen_ctx.new_write_err(
+                        &self.id,
+                        "Length too large for length field",
+                        quote!(format!("Length {} too large for length field type {}", #dest_ident.len(), #dest_len_type)),
+                    );
+                finish = quote!{
+                    #dest_len_ident = #dest_ident.len().try_into().map_err(|_| #err) ?;
+                };
+            },
+            TlvEnd_::Terminator(tag) => {
+                finish = quote!{
+                    {
+                        let tlv_type__: #tag_type = #tag;
+                        let tlv_value__ = std:: vec:: Vec::< u8 >:: new();
+                        #write_entry
+                    }
+                };
+            },
+        }
+        return quote!{
+            #dest_ident = vec ![];
+            for tlv_elem__ in #source_ident {
+                let tlv_type__: #tag_type;
+                let tlv_value__: std:: vec:: Vec < u8 >;
+                match tlv_elem__ {
+                    #(#var_code) *
+                    //. .
+                    #type_name_ident:: Unknown(t, v) => {
+                        tlv_type__ = t;
+                        tlv_value__ = v;
+                    },
+                }
+                #write_entry
+            }
+            #finish
+        };
+    }
+
+    fn set_rust(&self, rust: Node) {
+        let mut mut_ = self.mut_.borrow_mut();
+        if let Some(r) = &mut_.rust {
+            if r.id() != rust.id() {
+                panic!("Rust end of {} already connected to node {}", self.id, r.id());
+            }
+        }
+        mut_.rust = Some(rust);
+    }
+
+    fn scope(&self) -> Scope {
+        return self.scope.clone();
+    }
+
+    fn id(&self) -> String {
+        return self.id.clone();
+    }
+
+    fn id_ident(&self) -> Ident {
+        return self.id_ident.clone();
+    }
+
+    fn rust_type(&self) -> TokenStream {
+        let type_name_ident = &self.type_name_ident;
+        return quote!(std:: vec:: Vec < #type_name_ident >);
+    }
+}
+
+#[derive(Clone, Trace, Finalize)]
+pub struct NodeTlv(pub(crate) Gc<NodeTlv_>);
+
+impl NodeTlv {
+    /// Add a structure prefix line like `#[...]` to the entry enum definition.  Call
+    /// like `t.add_type_attrs(quote!(#[derive(x,y,z)]))`.
+    pub fn add_type_attrs(&self, attrs: TokenStream) {
+        self.0.mut_.borrow_mut().type_attrs.push(attrs);
+    }
+
+    /// Define a known entry type.  `tag` is a literal that will be used in the match
+    /// case for the type value.  The value of matching entries is read from the
+    /// returned scope, which reads from only the entry's value bytes.  Reading fails
+    /// if the scope doesn't consume all of the value bytes.
+    pub fn known(&self, id: impl Into<String>, variant_name: impl Into<String>, tag: TokenStream) -> Scope {
+        let id = id.into();
+        let variant_name = variant_name.into();
+        if variant_name == "Unknown" {
+            panic!("Variant name Unknown in {} is reserved for unknown entry types", self.0.id);
+        }
+        let element = Scope::new(id, &self.0.scope.0.schema, None);
+        self.0.mut_.borrow_mut().variants.push(TlvVariant {
+            var_name: variant_name.clone(),
+            var_name_ident: variant_name.ident().expect("Couldn't convert variant name into a rust identifier"),
+            tag: tag,
+            element: element.clone(),
+        });
+        return element;
+    }
+
+    pub(crate) fn generate_type(&self) -> TokenStream {
+        let type_ident = &self.0.type_name_ident;
+        let tag_type = self.0.tag_type();
+        let bytes_type = rust_type_bytes();
+        let mut variants = vec![];
+        for v in &self.0.mut_.borrow().variants {
+            let var_ident = &v.var_name_ident;
+            let var_type_ident = &v.element.get_rust_root().rust_type();
+            variants.push(quote!{
+                #var_ident(#var_type_ident),
+            });
+        }
+        let attrs = &self.0.mut_.borrow().type_attrs;
+        return quote!{
+            #(#attrs) *
+            //. .
+            pub enum #type_ident {
+                #(#variants) *
+                //. .
+                Unknown(#tag_type, #bytes_type),
+            }
+        };
+    }
+}
+
+impl Into<Node> for NodeTlv {
+    fn into(self) -> Node {
+        return Node(Node_::Tlv(self));
+    }
+}
+
+derive_forward_node_methods!(NodeTlv);
@@ -1,8 +1,11 @@
 use std::{
+    rc::Rc,
+    cell::RefCell,
     collections::{
         HashMap,
         BTreeMap,
         HashSet,
+        BTreeSet,
         btree_map::{
             Entry,
         },
@@ -16,6 +19,7 @@ use gc::{
 };
 use proc_macro2::{
     TokenStream,
+    Ident,
 };
 use quote::{
     quote,
@@ -25,6 +29,8 @@ use quote::{
 use crate::{
     util::{
         offset_ident,
+        rename_ident,
+        snake_case,
         ToIdent,
     },
     node::{
@@ -35,6 +41,13 @@ use crate::{
         },
         node_object::NodeObj,
         node_enum::NodeEnum,
+        node_tlv::NodeTlv,
+        node_instance::{
+            NodeInstance,
+            NodeParam,
+            NodeParam_,
+            NodeParamMut_,
+        },
     },
     scope::Scope,
 };
@@ -55,10 +68,244 @@ pub struct Schema_ {
     pub(crate) top_scopes: BTreeMap<String, Scope>,
     pub(crate) objects: BTreeMap<String, Vec<NodeObj>>,
     pub(crate) enums: BTreeMap<String, Vec<NodeEnum>>,
+    pub(crate) tlvs: BTreeMap<String, NodeTlv>,
+    pub(crate) type_defs: BTreeMap<String, TypeDef>,
 }
 
 impl Schema_ { }
 
+#[derive(Trace, Finalize)]
+pub(crate) struct TypeDefMut_ {
+    pub(crate) params: Vec<NodeParam>,
+    /// Set if the definition is recursive
+    pub(crate) max_depth: Option<usize>,
+}
+
+#[derive(Trace, Finalize)]
+pub(crate) struct TypeDef_ {
+    pub(crate) id: String,
+    pub(crate) scope: Scope,
+    pub(crate) mut_: GcCell<TypeDefMut_>,
+}
+
+/// Unboxed instances whose values are stored directly in the scope's value, not
+/// behind an array, map, or other heap container.
+fn inline_instances(scope: &Scope) -> Vec<NodeInstance> {
+    let mut out = vec![];
+    let mut elements = vec![];
+    for node in &scope.0.serial_root.0.mut_.borrow().sub_segments {
+        match &node.0 {
+            Node_::Instance(n) => {
+                if !n.0.mut_.borrow().boxed {
+                    out.push(n.clone());
+                }
+            },
+            Node_::Optional(n) => elements.push(n.0.element.clone()),
+            Node_::Capture(n) => elements.push(n.0.element.clone()),
+            Node_::Decode(n) => elements.push(n.0.element.clone()),
+            Node_::Framed(n) => elements.push(n.0.element.clone()),
+            Node_::Enum(n) => {
+                let mut_ = n.0.mut_.borrow();
+                elements.extend(mut_.variants.iter().map(|v| v.element.clone()));
+                elements.extend(mut_.default_variant.iter().map(|v| v.element.clone()));
+            },
+            _ => { },
+        }
+    }
+    for element in elements {
+        out.extend(inline_instances(&element));
+    }
+    return out;
+}
+
+impl TypeDef_ {
+    /// Panic if the definition's value would contain itself, via an unboxed instance
+    /// outside of any container.
+    fn check_boxed(&self) {
+        let mut seen = HashSet::new();
+        let mut stack = vec![self.scope.clone()];
+        while let Some(scope) = stack.pop() {
+            for instance in inline_instances(&scope) {
+                let def = &instance.0.def.0;
+                if def.id == self.id {
+                    panic!(
+                        "Instance {} of recursive definition {} isn't in an array or other container, so the type would contain itself; make it boxed with `NodeInstance::boxed`",
+                        instance.0.id,
+                        self.id
+                    );
+                }
+                if seen.insert(def.id.clone()) {
+                    stack.push(def.scope.clone());
+                }
+            }
+        }
+    }
+
+    /// The name of the generated function for this definition in the given context.
+    pub(crate) fn fn_ident(&self, write: bool, gen_ctx: &GenerateContext) -> Ident {
+        return gen_ctx.fn_name(write, &self.id).ident().unwrap();
+    }
+
+    fn generate(
+        &self,
+        schema: &Schema_,
+        fns: &Rc<RefCell<GeneratedFns>>,
+        variants: &BTreeSet<(bool, bool, bool)>,
+    ) -> Vec<TokenStream> {
+        let mut code = vec![];
+        let offset_ident = offset_ident();
+        let rust = self.scope.get_rust_root();
+        let rust_ident = rust.id_ident();
+        let rust_type_ident = rust.rust_type();
+        let serial_ident = &self.scope.0.serial_root.0.id_ident;
+        let mut params = vec![];
+        for param in &self.mut_.borrow().params {
+            let arg_ident = param.0.arg_ident();
+            let rust_type = &param.0.rust_type;
+            params.push(quote!(#arg_ident: #rust_type));
+        }
+        let max_depth = self.mut_.borrow().max_depth;
+        for (write, async_, low_heap) in variants {
+            let gen_ctx = GenerateContext {
+                low_heap: *low_heap,
+                async_: *async_,
+                fns: fns.clone(),
+            };
+            let method_ident = self.fn_ident(*write, &gen_ctx);
+            let async_kw = if *async_ {
+                quote!(async)
+            } else {
+                quote!()
+            };
+            if !*write {
+                let reader = gen_ctx.reader_bound(&schema.reader_bounds);
+                let errors = read_err_imports(*low_heap);
+                let method_code = generate_read(&gen_ctx, &self.scope);
+                let err_ident = gen_ctx.read_err_type();
+                let mut check_depth = quote!();
+                if let Some(max_depth) = max_depth {
+                    let err =
+                        gen_ctx.new_read_err(
+                            &self.id,
+                            "Maximum recursion depth exceeded",
+                            quote!(format!("Maximum recursion depth {} exceeded", #max_depth)),
+                        );
+                    check_depth = quote!{
+                        if depth__ > #max_depth {
+                            return Err(#err);
+                        }
+                    };
+                }
+                code.push(quote!{
+                    #async_kw fn #method_ident < R: #reader >(
+                        #serial_ident:& mut R,
+                        offset__:& mut usize,
+                        depth__: usize,
+                        #(#params,) *
+                    ) -> Result < #rust_type_ident,
+                    #err_ident > {
+                        #errors 
+                        //. .
+                        #check_depth 
+                        //. .
+                        let mut #offset_ident = * offset__;
+                        #method_code 
+                        //. .
+                        * offset__ = #offset_ident;
+                        return Ok(#rust_ident);
+                    }
+                });
+            } else {
+                let writer = gen_ctx.writer_bound();
+                let imports = gen_ctx.write_imports();
+                let method_code = generate_write(&gen_ctx, &self.scope);
+                code.push(quote!{
+                    #async_kw fn #method_ident < W: #writer >(
+                        #serial_ident:& mut W,
+                        offset__:& mut usize,
+                        #(#params,) * #rust_ident: #rust_type_ident,
+                    ) -> std:: io:: Result <() > {
+                        #imports 
+                        //. .
+                        let mut #offset_ident = * offset__;
+                        #method_code 
+                        //. .
+                        * offset__ = #offset_ident;
+                        return Ok(());
+                    }
+                });
+            }
+        }
+        return code;
+    }
+}
+
+/// A type definition that can be used from multiple scopes (see
+/// `Schema::type_def`).  Read and write functions are generated once for the
+/// definition and called wherever it's used.
+#[derive(Clone, Trace, Finalize)]
+pub struct TypeDef(pub(crate) Gc<TypeDef_>);
+
+impl TypeDef {
+    /// The scope to define the type in.  Set the rust root as usual.
+    pub fn scope(&self) -> Scope {
+        return self.0.scope.clone();
+    }
+
+    /// Declare a parameter of type `rust_type`.  The returned node can be used in the
+    /// definition's scope like any other value, and each instance must bind it with
+    /// `NodeInstance::bind`.  If the parameter has a consumer in the definition, when
+    /// writing it's an error if the consumer's value doesn't match the bound value.
+    pub fn param(&self, id: impl Into<String>, rust_type: TokenStream) -> NodeParam {
+        let id = id.into();
+        let scope = &self.0.scope;
+        let node = NodeParam(Gc::new(NodeParam_ {
+            scope: scope.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            rust_type: rust_type,
+            mut_: GcCell::new(NodeParamMut_ { rust: None }),
+        }));
+        scope.take_id(&id, Some(node.clone().into()));
+        scope.take_id(&format!("{}__arg", id), None);
+
+        // Visited when writing to check or copy the argument
+        scope.0.mut_.borrow_mut().serial_extra_roots.push(node.clone().into());
+        self.0.mut_.borrow_mut().params.push(node.clone());
+        return node;
+    }
+
+    /// Allow the definition to be instantiated within itself, directly or through
+    /// other definitions.  Reading returns an error if instances are nested more than
+    /// `max_depth` deep, to avoid overflowing the stack on malicious data.  Instances
+    /// not in an array or other container must be boxed with `NodeInstance::boxed`,
+    /// otherwise generation panics.
+    pub fn set_recursive(&self, max_depth: usize) {
+        self.0.mut_.borrow_mut().max_depth = Some(max_depth);
+    }
+
+    pub(crate) fn recursive(&self) -> bool {
+        return self.0.mut_.borrow().max_depth.is_some();
+    }
+}
+
+fn read_err_imports(low_heap: bool) -> TokenStream {
+    match low_heap {
+        true => {
+            return quote!{
+                use inarybay_runtime::lowheap_error::ReadErrCtx;
+                use inarybay_runtime::lowheap_error::ReadErrCtxIo;
+            };
+        },
+        false => {
+            return quote!{
+                use inarybay_runtime::error::ReadErrCtx;
+                use inarybay_runtime::error::ReadErrCtxIo;
+            };
+        },
+    }
+}
+
 /// A schema is the entrypoint for generating de/serializers.  A schema currently
 /// generates one module with all the types/functions.  Types will be
 /// deduplicated/reused within a schema.
@@ -83,38 +330,200 @@ pub struct GenerateConfig {
     pub simple_errors: bool,
 }
 
-pub(crate) struct GenerateContext {
+/// Functions generated for nested object and enum types, shared by all the
+/// functions in the module.
+#[derive(Default)]
+pub(crate) struct GeneratedFns {
+    /// The function source (with a placeholder name) by name, to reuse identical
+    /// functions.  `None` if the name is used by something else.
+    by_name: HashMap<String, Option<String>>,
+    code: Vec<TokenStream>,
+}
+
+impl GeneratedFns {
+    fn reserve(&mut self, name: String) {
+        self.by_name.insert(name, None);
+    }
+
+    /// Add the function built by `make` with the given name, or a numbered variant
+    /// of it if the name is taken by a different function.  Returns the name.
+    fn add(&mut self, name: &str, make: impl Fn(&Ident) -> TokenStream) -> Ident {
+        let key = make(&format_ident!("fn__")).to_string();
+        let mut i = 1usize;
+        loop {
+            let name = if i == 1 {
+                name.to_string()
+            } else {
+                format!("{}_{}", name, i)
+            };
+            match self.by_name.entry(name.clone()) {
+                std::collections::hash_map::Entry::Vacant(e) => {
+                    e.insert(Some(key));
+                    let ident = name.ident().unwrap();
+                    self.code.push(make(&ident));
+                    return ident;
+                },
+                std::collections::hash_map::Entry::Occupied(e) => {
+                    if e.get().as_ref() == Some(&key) {
+                        return name.ident().unwrap();
+                    }
+                },
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Settings for the code being generated.  Passed to plugin nodes (see
+/// `Scope::plugin`), along with helpers for producing code that works in every
+/// mode.
+pub struct GenerateContext {
     pub(crate) low_heap: bool,
     pub(crate) async_: bool,
+    pub(crate) fns: Rc<RefCell<GeneratedFns>>,
 }
 
 impl GenerateContext {
-    pub(crate) fn read_err_type(&self) -> TokenStream {
+    /// Whether the code is for async readers/writers (`await` is needed).
+    pub fn is_async(&self) -> bool {
+        return self.async_;
+    }
+
+    /// Whether errors are generated without allocation (see `GenerateConfig`).
+    pub fn is_low_heap(&self) -> bool {
+        return self.low_heap;
+    }
+
+    /// The name of a generated function for `name` (a type or definition) in this
+    /// context.
+    pub(crate) fn fn_name(&self, write: bool, name: &str) -> String {
+        return format!(
+            "{}_{}{}{}",
+            if write {
+                "write"
+            } else {
+                "read"
+            },
+            name,
+            if self.async_ {
+                "_async"
+            } else {
+                ""
+            },
+            if self.low_heap {
+                "_simple"
+            } else {
+                ""
+            }
+        );
+    }
+
+    pub(crate) fn reader_bound(&self, reader_bounds: &ReaderBounds) -> TokenStream {
+        match (reader_bounds, self.async_) {
+            (ReaderBounds::None, false) => return quote!(std::io::Read),
+            (ReaderBounds::Buffered, false) => return quote!(std::io::BufRead),
+            (ReaderBounds::None, true) => return quote!(
+                inarybay_runtime::async_::AsyncReadExt + std:: marker:: Unpin
+            ),
+            (ReaderBounds::Buffered, true) => return quote!(
+                inarybay_runtime::async_::AsyncBufReadExt + std:: marker:: Unpin
+            ),
+        }
+    }
+
+    pub(crate) fn writer_bound(&self) -> TokenStream {
+        if self.async_ {
+            return quote!(inarybay_runtime:: async_:: AsyncWriteExt + std:: marker:: Unpin);
+        } else {
+            return quote!(std:: io:: Write);
+        }
+    }
+
+    pub(crate) fn write_imports(&self) -> TokenStream {
+        let err_imports = match self.low_heap {
+            true => quote!(use inarybay_runtime::lowheap_error::WriteErrCtx;),
+            false => quote!(use inarybay_runtime::error::WriteErrCtx;),
+        };
+        if self.async_ {
+            return quote!{
+                use inarybay_runtime::async_::AsyncWriteExt;
+                #err_imports
+            };
+        } else {
+            return quote!{
+                use std::io::Write;
+                #err_imports
+            };
+        }
+    }
+
+    /// Add a function for the type `type_name` to the module, returning the name to
+    /// call it by.  `serial_ident` is renamed so functions for the same type in
+    /// different scopes can be shared.
+    pub(crate) fn add_fn(
+        &self,
+        write: bool,
+        type_name: &str,
+        serial_ident: &Ident,
+        generics: TokenStream,
+        args: Vec<TokenStream>,
+        ret: TokenStream,
+        body: TokenStream,
+    ) -> Ident {
+        let name = self.fn_name(write, &snake_case(type_name));
+        let async_kw = if self.async_ {
+            quote!(async)
+        } else {
+            quote!()
+        };
+        let args = quote!(#(#args,) *);
+        let args = rename_ident(args, serial_ident, &format_ident!("serial__"));
+        let body = rename_ident(body, serial_ident, &format_ident!("serial__"));
+        return self.fns.borrow_mut().add(&name, |ident| quote!{
+            #async_kw fn #ident #generics(#args) -> #ret {
+                #body
+            }
+        });
+    }
+
+    pub(crate) fn read_imports(&self) -> TokenStream {
+        return read_err_imports(self.low_heap);
+    }
+
+    /// The type of errors returned by read functions.
+    pub fn read_err_type(&self) -> TokenStream {
         match self.low_heap {
             true => return quote!(inarybay_runtime::lowheap_error::ReadError),
             false => return quote!(inarybay_runtime::error::ReadError),
         }
     }
 
-    pub(crate) fn wrap_read(&self, node: &str, mut read: TokenStream) -> TokenStream {
+    /// Turn an expression producing a `std::io::Result` (or a future of one, when
+    /// async) into one producing the value, returning a read error for `node` on
+    /// failure.
+    pub fn wrap_read(&self, node: &str, mut read: TokenStream) -> TokenStream {
         read = self.wrap_async(read);
         read = quote!(#read.errorize_io(#node) ?);
         return read;
     }
 
-    pub(crate) fn wrap_async(&self, mut read: TokenStream) -> TokenStream {
+    /// Add `.await` to the expression if async.
+    pub fn wrap_async(&self, mut read: TokenStream) -> TokenStream {
         if self.async_ {
             read = quote!(#read.await);
         }
         return read;
     }
 
-    pub(crate) fn wrap_write(&self, mut write: TokenStream) -> TokenStream {
+    /// Like `wrap_read` but for write functions.
+    pub fn wrap_write(&self, mut write: TokenStream) -> TokenStream {
         write = self.wrap_async(write);
         return quote!(#write ?);
     }
 
-    pub(crate) fn new_read_err(&self, node: &str, lowheap_text: &str, text: TokenStream) -> TokenStream {
+    /// An expression creating a read error for `node`. `text` is a `String`
+    /// expression, and `lowheap_text` is used instead in low heap mode.
+    pub fn new_read_err(&self, node: &str, lowheap_text: &str, text: TokenStream) -> TokenStream {
         match self.low_heap {
             true => {
                 let err_type = self.read_err_type();
@@ -126,6 +535,29 @@ impl GenerateContext {
             },
         }
     }
+
+    /// The type of structured errors produced by write functions (returned wrapped
+    /// in a `std::io::Error`).
+    pub fn write_err_type(&self) -> TokenStream {
+        match self.low_heap {
+            true => return quote!(inarybay_runtime::lowheap_error::WriteError),
+            false => return quote!(inarybay_runtime::error::WriteError),
+        }
+    }
+
+    /// Like `new_read_err` but for write functions.  The expression is a
+    /// `std::io::Error` wrapping a `WriteError`.
+    pub fn new_write_err(&self, node: &str, lowheap_text: &str, text: TokenStream) -> TokenStream {
+        let err_type = self.write_err_type();
+        match self.low_heap {
+            true => {
+                return quote!(std:: io:: Error:: from(#err_type:: new(#node, #lowheap_text)));
+            },
+            false => {
+                return quote!(std:: io:: Error:: from(#err_type:: new(#node, #text)));
+            },
+        }
+    }
 }
 
 impl Schema {
@@ -137,6 +569,8 @@ impl Schema {
             top_scopes: BTreeMap::new(),
             objects: BTreeMap::new(),
             enums: BTreeMap::new(),
+            tlvs: BTreeMap::new(),
+            type_defs: BTreeMap::new(),
         })));
     }
 
@@ -157,6 +591,30 @@ impl Schema {
         return out;
     }
 
+    /// Define a type that can be used in multiple places with `Scope::instance`.
+    /// Build the type in the returned definition's scope.  The id is used in the
+    /// generated function names, so it must be unique within the schema.
+    pub fn type_def(&self, id: impl Into<String>) -> TypeDef {
+        let id = id.into();
+        let out = TypeDef(Gc::new(TypeDef_ {
+            id: id.clone(),
+            scope: Scope::new(&id, self, None),
+            mut_: GcCell::new(TypeDefMut_ {
+                params: vec![],
+                max_depth: None,
+            }),
+        }));
+        match self.0.borrow_mut().type_defs.entry(id.clone()) {
+            Entry::Vacant(e) => {
+                e.insert(out.clone());
+            },
+            Entry::Occupied(_) => {
+                panic!("Type definition id {} is not unique", id);
+            },
+        };
+        return out;
+    }
+
     /// Generate code for the schema.
     pub fn generate(&self) -> String {
         let self2 = self.0.borrow();
@@ -245,6 +703,9 @@ impl Schema {
                 }
             });
         }
+        for tlv in self2.tlvs.values() {
+            code.push(tlv.generate_type());
+        }
         for (name, objs) in &self2.objects {
             let first = objs.first().unwrap();
 
@@ -296,6 +757,58 @@ impl Schema {
                 }
             });
         }
+
+        // Generate functions for type definitions, for each variant used by the top
+        // level scopes: (write, async, low heap)
+        let mut def_variants = BTreeSet::new();
+        for scope in self2.top_scopes.values() {
+            let config = scope.0.mut_.borrow().generate_config.as_ref().unwrap().clone();
+            for write in [false, true] {
+                let enabled = if write {
+                    config.write
+                } else {
+                    config.read
+                };
+                if !enabled {
+                    continue;
+                }
+                if config.sync_ {
+                    def_variants.insert((write, false, config.simple_errors));
+                }
+                if config.async_ {
+                    def_variants.insert((write, true, config.simple_errors));
+                }
+            }
+        }
+        let fns = Rc::new(RefCell::new(GeneratedFns::default()));
+        for prefix in self2.top_scopes.keys() {
+            let prefix = if prefix.is_empty() {
+                "".to_string()
+            } else {
+                format!("{}_", prefix)
+            };
+            for name in ["read", "read_async", "write", "write_async"] {
+                fns.borrow_mut().reserve(format!("{}{}", prefix, name));
+            }
+        }
+        for def in self2.type_defs.values() {
+            for (write, async_, low_heap) in &def_variants {
+                let gen_ctx = GenerateContext {
+                    low_heap: *low_heap,
+                    async_: *async_,
+                    fns: fns.clone(),
+                };
+                fns.borrow_mut().reserve(gen_ctx.fn_name(*write, &def.0.id));
+            }
+        }
+        for def in self2.type_defs.values() {
+            if def.recursive() {
+                def.0.check_boxed();
+            }
+        }
+        for def in self2.type_defs.values() {
+            code.extend(def.0.generate(&self2, &fns, &def_variants));
+        }
         for (prefix, scope) in &self.0.borrow().top_scopes {
             let prefix = if prefix.is_empty() {
                 "".to_string()
@@ -309,24 +822,12 @@ impl Schema {
             let serial_ident = &scope.0.serial_root.0.id_ident;
             let config = scope.0.mut_.borrow().generate_config.as_ref().unwrap().clone();
             if config.read {
-                let errors = match config.simple_errors {
-                    true => {
-                        quote!{
-                            use inarybay_runtime::lowheap_error::ReadErrCtx;
-                            use inarybay_runtime::lowheap_error::ReadErrCtxIo;
-                        }
-                    },
-                    false => {
-                        quote!{
-                            use inarybay_runtime::error::ReadErrCtx;
-                            use inarybay_runtime::error::ReadErrCtxIo;
-                        }
-                    },
-                };
+                let errors = read_err_imports(config.simple_errors);
                 if config.sync_ {
                     let gen_ctx = GenerateContext {
                         low_heap: config.simple_errors,
                         async_: false,
+                        fns: fns.clone(),
                     };
                     let reader = match &self.0.borrow().reader_bounds {
                         ReaderBounds::None => quote!(std::io::Read),
@@ -341,6 +842,7 @@ impl Schema {
                             #errors 
                             //. .
                             let mut #offset_ident = 0usize;
+                            let depth__ = 0usize;
                             #method_code 
                             //. .
                             return Ok(#rust_ident);
@@ -351,6 +853,7 @@ impl Schema {
                     let gen_ctx = GenerateContext {
                         low_heap: config.simple_errors,
                         async_: true,
+                        fns: fns.clone(),
                     };
                     let reader = match &self.0.borrow().reader_bounds {
                         ReaderBounds::None => quote!(inarybay_runtime::async_::AsyncReadExt),
@@ -367,6 +870,7 @@ impl Schema {
                             #errors 
                             //. .
                             let mut #offset_ident = 0usize;
+                            let depth__ = 0usize;
                             #method_code 
                             //. .
                             return Ok(#rust_ident);
@@ -379,15 +883,18 @@ impl Schema {
                     let gen_ctx = GenerateContext {
                         low_heap: config.simple_errors,
                         async_: false,
+                        fns: fns.clone(),
                     };
                     let method_ident = format_ident!("{}write", prefix);
+                    let imports = gen_ctx.write_imports();
                     let method_code = generate_write(&gen_ctx, scope);
                     code.push(quote!{
                         pub fn #method_ident < W: std:: io:: Write >(
                             #serial_ident:& mut W,
                             #rust_ident: #rust_type_ident,
                         ) -> std:: io:: Result <() > {
-                            use std::io::Write;
+                            #imports 
+                            //. .
                             let mut #offset_ident = 0usize;
                             //. .
                             #method_code 
@@ -400,15 +907,18 @@ impl Schema {
                     let gen_ctx = GenerateContext {
                         low_heap: config.simple_errors,
                         async_: true,
+                        fns: fns.clone(),
                     };
                     let method_ident = format_ident!("{}write_async", prefix);
+                    let imports = gen_ctx.write_imports();
                     let method_code = generate_write(&gen_ctx, scope);
                     code.push(quote!{
                         pub async fn #method_ident < W: inarybay_runtime:: async_:: AsyncWriteExt + std:: marker:: Unpin >(
                             #serial_ident:& mut W,
                             #rust_ident: #rust_type_ident,
                         ) -> std:: io:: Result <() > {
-                            use inarybay_runtime::async_::AsyncWriteExt;
+                            #imports 
+                            //. .
                             let mut #offset_ident = 0usize;
                             //. .
                             #method_code 
@@ -419,6 +929,8 @@ impl Schema {
                 }
             }
         }
+        code.extend(self.generate_trait_impls(&self2));
+        code.append(&mut fns.borrow_mut().code);
         let stream = quote!{
             #(#code) *
         };
@@ -438,6 +950,89 @@ impl Schema {
             .rendered;
     }
 
+    /// Implement the runtime traits (`inarybay_runtime::traits`) for the rust root
+    /// types of top level scopes, using the generated functions.  Types that aren't
+    /// defined in the schema, or are the root of multiple top level scopes, are
+    /// skipped.
+    fn generate_trait_impls(&self, self2: &Schema_) -> Vec<TokenStream> {
+        let mut roots: BTreeMap<String, Vec<(&String, &Scope)>> = BTreeMap::new();
+        for (prefix, scope) in &self2.top_scopes {
+            roots.entry(scope.get_rust_root().rust_type().to_string()).or_default().push((prefix, scope));
+        }
+        let mut code = vec![];
+        for (type_name, scopes) in roots {
+            if !self2.objects.contains_key(&type_name) && !self2.enums.contains_key(&type_name) &&
+                !self2.tlvs.contains_key(&type_name) {
+                continue;
+            }
+            let [(prefix, scope)] = scopes.as_slice() else {
+                continue;
+            };
+            let prefix = if prefix.is_empty() {
+                "".to_string()
+            } else {
+                format!("{}_", prefix)
+            };
+            let type_ident = scope.get_rust_root().rust_type();
+            let config = scope.0.mut_.borrow().generate_config.as_ref().unwrap().clone();
+            let err_ident = if config.simple_errors {
+                quote!(inarybay_runtime::lowheap_error::ReadError)
+            } else {
+                quote!(inarybay_runtime::error::ReadError)
+            };
+            if config.read && config.sync_ {
+                let method_ident = format_ident!("{}read", prefix);
+                code.push(quote!{
+                    impl inarybay_runtime:: traits:: Decode for #type_ident {
+                        type Error = #err_ident;
+                        fn decode < R: std:: io:: BufRead >(source:& mut R) -> Result < Self,
+                        Self:: Error > {
+                            return #method_ident(source);
+                        }
+                    }
+                });
+            }
+            if config.read && config.async_ {
+                let method_ident = format_ident!("{}read_async", prefix);
+                code.push(quote!{
+                    impl inarybay_runtime:: traits:: AsyncDecode for #type_ident {
+                        type Error = #err_ident;
+                        async fn decode_async < R: inarybay_runtime:: async_:: AsyncBufRead + std:: marker:: Unpin >(
+                            source:& mut R
+                        ) -> Result < Self,
+                        Self:: Error > {
+                            return #method_ident(source).await;
+                        }
+                    }
+                });
+            }
+            if config.write && config.sync_ {
+                let method_ident = format_ident!("{}write", prefix);
+                code.push(quote!{
+                    impl inarybay_runtime:: traits:: Encode for #type_ident {
+                        fn encode < W: std:: io:: Write >(self, dest:& mut W) -> std:: io:: Result <() > {
+                            return #method_ident(dest, self);
+                        }
+                    }
+                });
+            }
+            if config.write && config.async_ {
+                let method_ident = format_ident!("{}write_async", prefix);
+                code.push(quote!{
+                    impl inarybay_runtime:: traits:: AsyncEncode for #type_ident {
+                        async fn encode_async < W: inarybay_runtime:: async_:: AsyncWrite + std:: marker:: Unpin >(
+                            self,
+                            dest:& mut W
+                        ) -> std:: io:: Result <() > {
+                            return #method_ident(dest, self).await;
+                        }
+                    }
+                });
+            }
+        }
+        return code;
+    }
+
     /// Add an import line to the generated module. Deduplicated by naive
     /// stringification.
     pub fn add_import(&self, import: TokenStream) {
@@ -474,6 +1069,23 @@ pub(crate) fn generate_read(gen_ctx: &GenerateContext, scope: &Scope) -> TokenSt
             let mut #id: #rust_type;
         });
     }
+    let checksums =
+        scope
+            .0
+            .serial_root
+            .0
+            .mut_
+            .borrow()
+            .sub_segments
+            .iter()
+            .filter_map(|n| match &n.0 {
+                Node_::Checksum(c) => Some(c.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+    for checksum in &checksums {
+        code.push(checksum.0.generate_read_init());
+    }
     while let Some((node, first_visit)) = stack.pop() {
         if first_visit {
             if !seen.insert(node.id()) {
@@ -487,9 +1099,20 @@ pub(crate) fn generate_read(gen_ctx: &GenerateContext, scope: &Scope) -> TokenSt
             }
         } else {
             // Post-deps, now do main processing
-            code.push(node.0.generate_read(gen_ctx));
+            let mut node_code = node.0.generate_read(gen_ctx);
+            for checksum in &checksums {
+                if checksum.0.covers(&node.id()) {
+                    node_code = checksum.0.wrap_covered_read(node_code);
+                }
+            }
+            code.push(node_code);
         }
     }
+
+    // Covered segments may be anywhere in the scope, so verify once everything's read
+    for checksum in &checksums {
+        code.push(checksum.0.generate_read_verify(gen_ctx));
+    }
     return quote!(#(#code) *);
 }
 
@@ -498,10 +1121,10 @@ pub(crate) fn generate_write(gen_ctx: &GenerateContext, scope: &Scope) -> TokenS
     // segment/serial root)
     let mut seen = HashSet::new();
     let mut stack: Vec<(Node, bool)> = vec![];
+    stack.push((scope.0.serial_root.clone().into(), true));
     for c in &scope.0.mut_.borrow().serial_extra_roots {
         stack.push((c.clone().into(), true));
     }
-    stack.push((scope.0.serial_root.clone().into(), true));
     let mut code = vec![];
     let rust_root_id = scope.get_rust_root().id();
     for (id, info) in &scope.0.mut_.borrow().level_ids {
@@ -543,3 +1166,103 @@ pub(crate) fn generate_write(gen_ctx: &GenerateContext, scope: &Scope) -> TokenS
     }
     return quote!(#(#code) *);
 }
+
+/// Nested scopes producing an object can be read/written in their own function if
+/// they don't share values with enclosing scopes.
+fn nested_fn_type(scope: &Scope) -> Option<String> {
+    if !scope_isolated(scope) {
+        return None;
+    }
+    match &scope.get_rust_root().0 {
+        Node_::Obj(obj) => return Some(obj.0.type_name.clone()),
+        _ => return None,
+    }
+}
+
+/// True if the scope's generated code doesn't use any values from enclosing scopes
+/// or assign values in them.
+pub(crate) fn scope_isolated(scope: &Scope) -> bool {
+    let mut_ = scope.0.mut_.borrow();
+    return !mut_.has_external_deps && mut_.array_externals.is_empty() && mut_.array_indices.is_empty() &&
+        mut_.observed_externals.is_empty() &&
+        scope.0.serial_root.0.mut_.borrow().lifted_serial_deps.is_empty();
+}
+
+/// Like `generate_read`, for a scope nested in another node.  Scopes producing
+/// objects are read in their own function, called here, when possible.
+pub(crate) fn generate_nested_read(gen_ctx: &GenerateContext, scope: &Scope) -> TokenStream {
+    let Some(type_name) = nested_fn_type(scope) else {
+        return generate_read(gen_ctx, scope);
+    };
+    let offset_ident = offset_ident();
+    let rust = scope.get_rust_root();
+    let rust_ident = rust.id_ident();
+    let rust_type = rust.rust_type();
+    let serial_ident = &scope.0.serial_root.0.id_ident;
+    let reader = gen_ctx.reader_bound(&scope.0.schema.0.borrow().reader_bounds);
+    let err_ident = gen_ctx.read_err_type();
+    let errors = gen_ctx.read_imports();
+    let method_code = generate_read(gen_ctx, scope);
+    let method =
+        gen_ctx.add_fn(
+            false,
+            &type_name,
+            serial_ident,
+            quote!(< R: #reader >),
+            vec![quote!(#serial_ident:& mut R), quote!(offset__:& mut usize), quote!(depth__: usize)],
+            quote!(Result < #rust_type, #err_ident >),
+            quote!{
+                #errors 
+                //. .
+                let mut #offset_ident = * offset__;
+                #method_code 
+                //. .
+                * offset__ = #offset_ident;
+                return Ok(#rust_ident);
+            },
+        );
+    let read = gen_ctx.wrap_async(quote!(#method(&mut * #serial_ident, &mut #offset_ident, depth__)));
+    return quote!{
+        let #rust_ident = #read ?;
+    };
+}
+
+/// Like `generate_write`, for a scope nested in another node.  See
+/// `generate_nested_read`.
+pub(crate) fn generate_nested_write(gen_ctx: &GenerateContext, scope: &Scope) -> TokenStream {
+    let Some(type_name) = nested_fn_type(scope) else {
+        return generate_write(gen_ctx, scope);
+    };
+    let offset_ident = offset_ident();
+    let rust = scope.get_rust_root();
+    let rust_ident = rust.id_ident();
+    let rust_type = rust.rust_type();
+    let serial_ident = &scope.0.serial_root.0.id_ident;
+    let writer = gen_ctx.writer_bound();
+    let imports = gen_ctx.write_imports();
+    let method_code = generate_write(gen_ctx, scope);
+    let method =
+        gen_ctx.add_fn(
+            true,
+            &type_name,
+            serial_ident,
+            quote!(< W: #writer >),
+            vec![quote!(#serial_ident:& mut W), quote!(offset__:& mut usize), quote!(#rust_ident: #rust_type)],
+            quote!(std:: io:: Result <() >),
+            quote!{
+                #imports 
+                //. .
+                let mut #offset_ident = * offset__;
+                #method_code 
+                //. .
+                * offset__ = #offset_ident;
+                return Ok(());
+            },
+        );
+    let write = gen_ctx.wrap_write(quote!(#method(&mut #serial_ident, &mut #offset_ident, #rust_ident)));
+    return quote!{
+        // The destination may be a buffer or a reference to one
+        let mut #serial_ident = #serial_ident;
+        #write;
+    };
+}
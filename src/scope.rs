@@ -39,6 +39,7 @@ use crate::{
             Node,
             NodeMethods,
             RedirectRef,
+            Node_,
         },
         node_const::{
             NodeConst_,
@@ -99,16 +100,102 @@ use crate::{
             NodeObj_,
             NodeObjMut_,
         },
+        node_optional::{
+            NodeOptional,
+            NodeOptional_,
+            NodeOptionalMut_,
+            OptionalCondition,
+        },
+        node_int_transform::{
+            NodeIntTransform,
+            NodeIntTransform_,
+            NodeIntTransformMut_,
+            widened_int_type,
+        },
+        node_array_external::{
+            NodeArrayExternal,
+            NodeArrayExternal_,
+            NodeArrayExternalMut_,
+        },
+        node_instance::{
+            NodeInstance,
+            NodeInstance_,
+            NodeInstanceMut_,
+        },
+        node_delegate::{
+            NodeDelegate,
+            NodeDelegate_,
+            NodeDelegateMut_,
+        },
+        node_plugin::{
+            NodePlugin,
+            NodePlugin_,
+            NodePluginMut_,
+            Plugin,
+        },
+        node_array_index::{
+            NodeArrayIndex,
+            NodeArrayIndex_,
+        },
+        node_fan_out::{
+            NodeFanOut,
+            NodeFanOut_,
+            NodeFanOutMut_,
+        },
+        node_checksum::{
+            NodeChecksum,
+            NodeChecksum_,
+            NodeChecksumMut_,
+        },
+        node_capture::{
+            NodeCapture,
+            NodeCapture_,
+            NodeCaptureMut_,
+            NodeCaptureRaw,
+            NodeCaptureRaw_,
+            NodeCaptureRawMut_,
+        },
+        node_decode::{
+            NodeDecode,
+            NodeDecode_,
+            NodeDecodeMut_,
+        },
+        node_framed::{
+            NodeFramed,
+            NodeFramed_,
+            NodeFramedMut_,
+        },
+        node_tlv::{
+            NodeTlv,
+            NodeTlv_,
+            NodeTlvMut_,
+            TlvEnd_,
+            tlv_int_type,
+        },
+        node_stride_array::{
+            NodeStrideArray,
+            NodeStrideArray_,
+            NodeStrideArrayMut_,
+        },
+        node_dynamic_map::{
+            NodeDynamicMap,
+            NodeDynamicMap_,
+            NodeDynamicMapMut_,
+        },
     },
     util::{
         BVec,
         LateInit,
         ToIdent,
+        is_int_type,
+        int_type_max,
+        rust_type_bytes,
     },
     schema::{
         ReaderBounds,
         Schema,
         GenerateConfig,
+        TypeDef,
     },
 };
 
@@ -118,6 +205,132 @@ pub enum Endian {
     Little,
 }
 
+/// Reversible arithmetic for `Scope::int_transform`.  The read direction is
+/// described, writing uses the inverse.
+#[derive(Trace, Finalize)]
+pub enum IntOp {
+    Add(u64),
+    Sub(u64),
+    Mul(u64),
+    /// Reading fails if the value isn't a multiple of the divisor.
+    Div(u64),
+}
+
+/// Checksum algorithms for `Scope::checksum`.  The implementations are in the
+/// `checksum` module of the runtime crate.
+pub enum Checksum {
+    /// CRC-32 (ISO-HDLC, as used by zip, png, ethernet)
+    Crc32,
+    Adler32,
+    /// CRC-16/CCITT-FALSE
+    Crc16Ccitt,
+    /// RFC 1071 internet checksum
+    Internet,
+    /// Xor of all bytes
+    Xor,
+    /// The path of a type implementing `inarybay_runtime::checksum::Checksum`
+    Custom(TokenStream),
+}
+
+/// How a captured value is written.
+pub enum CaptureWrite {
+    /// Always serialize the value.
+    Reserialize,
+    /// Write the raw bytes if present and they still read as the value, otherwise
+    /// serialize the value.  The raw bytes are read again when writing to check
+    /// this, so the value type must implement `PartialEq`.
+    Verbatim,
+}
+
+/// A reversible byte transformation applied to nested data.  The built-in
+/// compression codecs require the corresponding `inarybay-runtime` feature.
+pub enum Codec {
+    /// Zlib (RFC 1950), feature `zlib`
+    Zlib,
+    /// Raw deflate (RFC 1951), feature `deflate`
+    Deflate,
+    /// Gzip (RFC 1952), feature `gzip`
+    Gzip,
+    /// LZ4 frame format, feature `lz4`
+    Lz4,
+    /// Zstandard, feature `zstd`
+    Zstd,
+    /// Xor with a repeating key
+    Xor(Vec<u8>),
+    /// An expression producing a value implementing `inarybay_runtime::codec::Codec`
+    Custom(TokenStream),
+}
+
+/// Byte stuffing schemes for framed data.
+pub enum Framing {
+    /// Consistent overhead byte stuffing, terminated by `0x00`
+    Cobs,
+    /// SLIP (RFC 1055), terminated by `0xC0`
+    Slip,
+    /// HDLC-style byte stuffing (RFC 1662), enclosed by `0x7E` flags
+    Hdlc,
+}
+
+/// Options for `delimited_bytes_ext`.
+#[derive(Clone, Default)]
+pub struct DelimitedConfig {
+    /// Any of these ends the data when reading (the longest wins if several match).
+    /// The first is written.
+    pub delimiters: Vec<Vec<u8>>,
+    /// When writing, this byte is placed before the escape byte and the first byte of
+    /// any delimiter in the data.  When reading, the byte following it is always
+    /// treated as data.
+    pub escape: Option<u8>,
+    /// If true, reaching the end of data before a delimiter is an error when reading.
+    /// Otherwise all the remaining data is used.
+    pub strict: bool,
+    /// If true (and there's no `escape`), writing data that contains a delimiter is an
+    /// error.  Otherwise the data is written as is, and won't read back the same.
+    pub error_on_delimiter: bool,
+}
+
+/// Where a TLV sequence ends, see `TlvConfig`.
+pub enum TlvEnd {
+    /// At the end of the data
+    Eof,
+    /// After a number of bytes specified by a previous integer value.  When writing,
+    /// the integer is set from the size of the sequence.
+    Len(Node),
+    /// After an entry with this type (a literal).  The terminating entry is written
+    /// with an empty value.
+    Terminator(TokenStream),
+}
+
+/// The layout of entries in a TLV sequence, for `tlv`.
+pub struct TlvConfig {
+    /// Size of the type field in bytes: 1, 2, 4, or 8
+    pub type_bytes: usize,
+    /// Size of the length field in bytes: 1, 2, 4, or 8
+    pub len_bytes: usize,
+    /// Endianness of the type and length fields
+    pub endian: Endian,
+    /// If true, the length field is before the type field
+    pub length_first: bool,
+    /// If true, the length counts the size of the type field as well as the value
+    pub len_includes_type: bool,
+    pub end: TlvEnd,
+}
+
+/// The Rust map type for `dynamic_map`.
+pub enum MapType {
+    /// `BTreeMap`, written in key order
+    BTree,
+    /// `HashMap`, written in key order (the key must be `Ord`)
+    Hash,
+}
+
+fn check_int(id: &str, serial: &Node) {
+    let rust_type = serial.rust_type().to_string();
+    if !is_int_type(&rust_type) {
+        panic!("Input {} to {} must be an integer, but it is {}", serial.id(), id, rust_type);
+    }
+}
+
 pub trait BecomesByteVec {
     fn get(&self) -> Node;
 }
@@ -147,15 +360,26 @@ pub(crate) struct EscapableParentEnum {
     pub(crate) parent: Scope,
 }
 
+/// An element scope written zero or more times: an array's elements, or an
+/// optional's value.
+#[derive(Clone, Trace, Finalize)]
+pub(crate) struct EscapableParentArray {
+    pub(crate) array: Node,
+    pub(crate) element: Scope,
+    pub(crate) parent: Scope,
+}
+
 #[derive(Clone, Trace, Finalize)]
 pub(crate) enum EscapableParent {
     None,
     Enum(EscapableParentEnum),
+    Array(EscapableParentArray),
 }
 
 #[derive(Clone, Trace, Finalize)]
 pub(crate) enum SomeEscapableParent {
     Enum(EscapableParentEnum),
+    Array(EscapableParentArray),
 }
 
 #[derive(Clone, Copy)]
@@ -190,15 +414,38 @@ struct Range_ {
 #[derive(Clone)]
 pub struct Range(Gc<GcCell<Range_>>);
 
+impl Range {
+    /// The node owning the range's bytes, for example to cover them with a checksum.
+    pub fn node(&self) -> Node {
+        return self.0.borrow().serial.clone().into();
+    }
+}
+
 #[derive(Trace, Finalize)]
 pub(crate) struct ScopeMut_ {
     #[unsafe_ignore_trace]
     pub(crate) generate_config: Option<GenerateConfig>,
     pub(crate) rust_root: Option<Node>,
     pub(crate) escapable_parent: EscapableParent,
-    pub(crate) rust_extra_roots: Vec<NodeConst>,
+    pub(crate) rust_extra_roots: Vec<Node>,
     pub(crate) serial_extra_roots: Vec<Node>,
     pub(crate) has_external_deps: bool,
+    /// Copies of values from outside the array, if this is an array element scope
+    pub(crate) array_externals: Vec<NodeArrayExternal>,
+    /// Current element index nodes, if this is an array element scope
+    pub(crate) array_indices: Vec<NodeArrayIndex>,
+    /// Values from the escapable parent's scope observed within this scope, which
+    /// must be read/written before the parent node
+    pub(crate) observed_externals: Vec<Node>,
+    /// Connections made via `connect_value` for nodes in this scope, by id: the
+    /// enum the consumers are in (if any), whether the consumer is an array using the
+    /// value in its elements, and the node (the value or a fan out) they were
+    /// connected to
+    #[unsafe_ignore_trace]
+    pub(crate) rust_connected: HashMap<String, Vec<(Option<String>, bool, Node)>>,
+    /// Id of the first trailing value, after which only more trailing values can be
+    /// added
+    pub(crate) trailing: Option<String>,
     #[unsafe_ignore_trace]
     pub(crate) level_ids: BTreeMap<String, Option<Node>>,
 }
@@ -221,8 +468,8 @@ pub(crate) struct Scope_ {
 /// processing within the loop.
 ///
 /// `Scope` has methods for creating nodes that will be evaulated within the scope.
-/// In some nesting contexts (enum, object, _not_ array) nodes in an inner scope
-/// can refer to nodes in an outer scope.
+/// In some nesting contexts (enum, object, array) nodes in an inner scope can refer
+/// to nodes in an outer scope.
 ///
 /// The `Scope` also has a defined serial-side and rust-side root which are
 /// starting points for the serialization and deserialization graphs respectively.
@@ -256,6 +503,11 @@ impl Scope {
                 rust_extra_roots: vec![],
                 serial_extra_roots: vec![],
                 has_external_deps: false,
+                array_externals: vec![],
+                array_indices: vec![],
+                observed_externals: vec![],
+                rust_connected: HashMap::new(),
+                trailing: None,
                 level_ids: BTreeMap::new(),
             }),
         }));
@@ -445,10 +697,53 @@ impl Scope {
         self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(serial.clone().into());
     }
 
+    /// Apply reversible arithmetic to an integer, like converting a length in 32-bit
+    /// words to a length in bytes.  The result is a `u64` (`i64` for signed inputs,
+    /// 128-bit inputs stay 128-bit) so scaling doesn't overflow the input type.
+    /// Reading fails if the result overflows (or for division, isn't exact), and
+    /// writing fails if the value can't be produced by the transform or doesn't fit
+    /// back in the input type.
+    pub fn int_transform(&self, id: impl Into<String>, serial: impl Into<Node>, op: IntOp) -> NodeIntTransform {
+        let id = id.into();
+        let serial = serial.into();
+        check_int(&id, &serial);
+        match op {
+            IntOp::Mul(0) | IntOp::Div(0) => {
+                panic!("Int transform {} can't multiply or divide by 0", id);
+            },
+            _ => { },
+        }
+        let rust_type = serial.rust_type();
+        let operand = match &op {
+            IntOp::Add(v) | IntOp::Sub(v) | IntOp::Mul(v) | IntOp::Div(v) => *v,
+        };
+        if operand > int_type_max(&rust_type.to_string()) {
+            panic!("Int transform {} operand {} doesn't fit in the input type {}", id, operand, rust_type);
+        }
+        let node = NodeIntTransform(Gc::new(NodeIntTransform_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            op: op,
+            mut_: GcCell::new(NodeIntTransformMut_ {
+                serial: None,
+                rust: None,
+            }),
+            rust_type: widened_int_type(&rust_type.to_string()),
+            serial_type: rust_type,
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.connect_value(&serial, node.clone().into(), &mut node.0.mut_.borrow_mut().serial);
+        return node;
+    }
+
     /// Read/write a sequence of bytes whose length is determined dynamically by a
-    /// previous integer.
-    pub fn dynamic_bytes(&self, id: impl Into<String>, len: NodeInt) -> NodeDynamicBytes {
+    /// previous integer.  `len` can be any node with an integer Rust type, like `int`,
+    /// `int_transform` or a `custom` node.
+    pub fn dynamic_bytes(&self, id: impl Into<String>, len: impl Into<Node>) -> NodeDynamicBytes {
         let id = id.into();
+        let len = len.into();
+        check_int(&id, &len);
         let serial = self.seg(&id);
         let node = NodeDynamicBytes(Gc::new(NodeDynamicBytes_ {
             scope: self.clone(),
@@ -463,32 +758,59 @@ impl Scope {
         }));
         self.take_id(&id, Some(node.clone().into()));
         self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
-        self.lift_connect(
-            &self.get_ancestry_to(&len),
-            &len,
-            node.clone().into(),
-            &mut node.0.mut_.borrow_mut().serial_len,
-        );
+        self.connect_value(&len, node.clone().into(), &mut node.0.mut_.borrow_mut().serial_len);
         return node;
     }
 
     /// Read/write a sequence of bytes until the specified delimiter sequence of bytes.
     pub fn delimited_bytes(&self, id: impl Into<String>, delimiter: &[u8]) -> NodeDelimitedBytes {
+        return self.delimited_bytes_ext(id, DelimitedConfig {
+            delimiters: vec![delimiter.to_vec()],
+            ..Default::default()
+        });
+    }
+
+    /// Like `delimited_bytes` but with escaping, multiple delimiters, and stricter
+    /// checks.  See `DelimitedConfig`.
+    pub fn delimited_bytes_ext(&self, id: impl Into<String>, config: DelimitedConfig) -> NodeDelimitedBytes {
         self.0.schema.0.borrow_mut().reader_bounds = ReaderBounds::Buffered;
         let id = id.into();
-        let serial = self.seg(&id);
-        let mut delim_els = vec![];
-        for b in delimiter {
-            delim_els.push(quote!(#b));
+        if config.delimiters.is_empty() || config.delimiters.iter().any(|d| d.is_empty()) {
+            panic!("Delimiters for {} must be non-empty", id);
         }
+        {
+            // Like `inarybay_runtime::delimited::contains_delimiter` with empty data
+            let write_delim = &config.delimiters[0];
+            for d in &config.delimiters {
+                if write_delim
+                    .windows(d.len())
+                    .enumerate()
+                    .any(|(start, w)| w == d.as_slice() && start + d.len() < write_delim.len()) {
+                    panic!(
+                        "Delimiter {:?} of {} is found inside the written delimiter {:?}, so data couldn't be read back",
+                        d,
+                        id,
+                        write_delim
+                    );
+                }
+            }
+        }
+        if let Some(escape) = config.escape {
+            if config.delimiters.iter().any(|d| d.contains(&escape)) {
+                panic!("Escape byte of {} can't be part of a delimiter", id);
+            }
+        }
+        let serial = self.seg(&id);
         let node = NodeDelimitedBytes(Gc::new(NodeDelimitedBytes_ {
             scope: self.clone(),
             id: id.clone(),
             id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
             serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
             serial: serial.clone(),
-            delim_len: delimiter.len(),
-            delim_bytes: quote!(&[#(#delim_els,) *]),
+            delimiters: config.delimiters,
+            escape: config.escape,
+            strict: config.strict,
+            error_on_delimiter: config.error_on_delimiter,
             mut_: GcCell::new(NodeDelimitedBytesMut_ { rust: None }),
         }));
         self.take_id(&id, Some(node.clone().into()));
@@ -515,10 +837,67 @@ impl Scope {
         return node;
     }
 
+    /// Read/write a value using a type definition (see `Schema::type_def`).  The
+    /// definition's generated functions are called here.
+    pub fn instance(&self, id: impl Into<String>, def: &TypeDef) -> NodeInstance {
+        let id = id.into();
+        let serial = self.seg(&id);
+        let node = NodeInstance(Gc::new(NodeInstance_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial.clone(),
+            def: def.clone(),
+            mut_: GcCell::new(NodeInstanceMut_ {
+                args: BTreeMap::new(),
+                boxed: false,
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        return node;
+    }
+
+    /// Read/write a value of a hand-written type.  The type must implement
+    /// `inarybay_runtime::traits::Delegate` (and `AsyncDelegate` for async code), which
+    /// is given the reader/writer directly, along with the current offset (when
+    /// writing, only in top level scopes).  Reading continues after however much the
+    /// implementation consumed.
+    pub fn delegate(&self, id: impl Into<String>, rust_type: TokenStream) -> NodeDelegate {
+        let id = id.into();
+        syn::parse2::<Path>(rust_type.clone()).expect("Rust type isn't a valid type");
+        let serial = self.seg(&id);
+        let node = NodeDelegate(Gc::new(NodeDelegate_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            rust_type: rust_type,
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial.clone(),
+            mut_: GcCell::new(NodeDelegateMut_ { rust: None }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        return node;
+    }
+
     /// Read/write an array of objects, with the length (number of objects) specified
-    /// by a previous integer value.
-    pub fn dynamic_array(&self, id: impl Into<String>, len: NodeInt) -> (NodeDynamicArray, Scope) {
+    /// by a previous integer value.  `len` can be any node with an integer Rust type.
+    /// Element scopes can refer to values from the prior element via
+    /// `NodeDynamicArray::previous`.
+    ///
+    /// Element scopes can also use values from outside the array.  When writing, the
+    /// value is taken from the elements, which must all agree with each other and with
+    /// the value's uses outside the array.  The value must also be used outside the
+    /// array (for example as an object field), to provide it when the array is empty.
+    pub fn dynamic_array(&self, id: impl Into<String>, len: impl Into<Node>) -> (NodeDynamicArray, Scope) {
         let id = id.into();
+        let len = len.into();
+        check_int(&id, &len);
         let serial = self.seg(&id);
         let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
         let node = NodeDynamicArray(Gc::new(NodeDynamicArray_ {
@@ -530,20 +909,479 @@ impl Scope {
             element: scope.clone(),
             mut_: GcCell::new(NodeDynamicArrayMut_ {
                 serial_len: None,
+                previous: vec![],
                 rust: None,
             }),
         }));
         self.take_id(&id, Some(node.clone().into()));
         self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
-        self.lift_connect(
-            &self.get_ancestry_to(&len),
-            &len,
-            node.clone().into(),
-            &mut node.0.mut_.borrow_mut().serial_len,
-        );
+        self.connect_value(&len, node.clone().into(), &mut node.0.mut_.borrow_mut().serial_len);
+        scope.0.mut_.borrow_mut().escapable_parent = EscapableParent::Array(EscapableParentArray {
+            array: node.clone().into(),
+            element: scope.clone(),
+            parent: self.clone(),
+        });
+        return (node, scope);
+    }
+
+    /// Read/write an array of key/value records as a map, with the number of records
+    /// specified by a previous integer value.  Each record is read from the returned
+    /// scope; set the key with `NodeDynamicMap::key` and the value with the scope's
+    /// `rust_root`.  If `error_on_duplicate` is true a repeated key is an error when
+    /// reading, otherwise the last record wins.
+    pub fn dynamic_map(
+        &self,
+        id: impl Into<String>,
+        len: impl Into<Node>,
+        map_type: MapType,
+        error_on_duplicate: bool,
+    ) -> (NodeDynamicMap, Scope) {
+        let id = id.into();
+        let len = len.into();
+        check_int(&id, &len);
+        let serial = self.seg(&id);
+        let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
+        let node = NodeDynamicMap(Gc::new(NodeDynamicMap_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            element: scope.clone(),
+            map_type: map_type,
+            error_on_duplicate: error_on_duplicate,
+            mut_: GcCell::new(NodeDynamicMapMut_ {
+                serial_len: None,
+                key: None,
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        self.connect_value(&len, node.clone().into(), &mut node.0.mut_.borrow_mut().serial_len);
+        scope.0.mut_.borrow_mut().escapable_parent = EscapableParent::Array(EscapableParentArray {
+            array: node.clone().into(),
+            element: scope.clone(),
+            parent: self.clone(),
+        });
+        return (node, scope);
+    }
+
+    /// Read/write an array of objects where each element occupies exactly `stride`
+    /// bytes, with the number of elements specified by a previous integer value.
+    ///
+    /// `stride` is a previous integer in this scope, which must also be used elsewhere
+    /// (the array only observes it).  When writing, each element is padded with zeros
+    /// to the stride, and an element larger than the stride is an error.
+    ///
+    /// If `preserve_tail` is true, the Rust value is a `Vec` of `(element, tail)` where
+    /// `tail` is the unparsed remainder of each element's bytes, which is written after
+    /// the element.  Otherwise the tail is skipped.
+    pub fn stride_array(
+        &self,
+        id: impl Into<String>,
+        len: impl Into<Node>,
+        stride: impl Into<Node>,
+        preserve_tail: bool,
+    ) -> (NodeStrideArray, Scope) {
+        let id = id.into();
+        let len = len.into();
+        let stride = stride.into();
+        check_int(&id, &len);
+        check_int(&id, &stride);
+        if stride.scope().0.id != self.0.id {
+            panic!("Stride {} of {} is not in the same scope", stride.id(), id);
+        }
+        let serial = self.seg(&id);
+        let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
+        let node = NodeStrideArray(Gc::new(NodeStrideArray_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            stride: stride,
+            preserve_tail: preserve_tail,
+            element: scope.clone(),
+            mut_: GcCell::new(NodeStrideArrayMut_ {
+                serial_len: None,
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        self.connect_value(&len, node.clone().into(), &mut node.0.mut_.borrow_mut().serial_len);
+        scope.0.mut_.borrow_mut().escapable_parent = EscapableParent::Array(EscapableParentArray {
+            array: node.clone().into(),
+            element: scope.clone(),
+            parent: self.clone(),
+        });
+        return (node, scope);
+    }
+
+    /// Get the index (`usize`) of the current element.  This scope must be an array
+    /// element scope (from `dynamic_array`, `dynamic_map`, or `stride_array`).  The
+    /// index can be used in conditions, custom nodes, error messages, etc.  Read
+    /// errors raised in element scopes record the index in `ReadError::index` either
+    /// way.
+    pub fn array_index(&self, id: impl Into<String>) -> NodeArrayIndex {
+        let id = id.into();
+        let is_array = match &self.0.mut_.borrow().escapable_parent {
+            EscapableParent::Array(a) => !matches!(&a.array.0, Node_::Optional(_)),
+            _ => false,
+        };
+        if !is_array {
+            panic!("Scope {} is not an array element scope, can't get index {}", self.0.id, id);
+        }
+        let node = NodeArrayIndex(Gc::new(NodeArrayIndex_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+        }));
+
+        // Declared by the array
+        self.take_id(&id, None);
+        self.take_id(&format!("{}__next", id), None);
+        self.0.mut_.borrow_mut().array_indices.push(node.clone());
+        return node;
+    }
+
+    /// Read/write an optional value (`Option` in Rust).  `flag` is a previously read
+    /// bool or integer; the value is read from the returned scope only if `flag` is
+    /// true (non-zero).  When writing, `flag` is set from whether the value is
+    /// `Some`.
+    ///
+    /// The returned scope (like those of `conditional` and `trailing`) can use values
+    /// from enclosing scopes, but they must also be used outside it so they can be
+    /// written when the value is `None`.
+    pub fn optional(&self, id: impl Into<String>, flag: impl Into<Node>) -> (NodeOptional, Scope) {
+        let id = id.into();
+        let flag = flag.into();
+        let flag_type = flag.rust_type().to_string();
+        if flag_type != "bool" && !is_int_type(&flag_type) {
+            panic!("Optional flag {} must be a bool or integer, but it is {}", flag.id(), flag_type);
+        }
+        let serial = self.seg(&id);
+        let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
+        let node = NodeOptional(Gc::new(NodeOptional_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            element: scope.clone(),
+            condition: OptionalCondition::Flag,
+            condition_deps: vec![],
+            mut_: GcCell::new(NodeOptionalMut_ {
+                serial_flag: None,
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        node.0.serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        self.connect_value(&flag, node.clone().into(), &mut node.0.mut_.borrow_mut().serial_flag);
+        scope.0.mut_.borrow_mut().escapable_parent = EscapableParent::Array(EscapableParentArray {
+            array: node.clone().into(),
+            element: scope.clone(),
+            parent: self.clone(),
+        });
+        return (node, scope);
+    }
+
+    /// Read/write an optional value (`Option` in Rust) whose presence depends on other
+    /// values, like fields only present in some versions of a format.
+    ///
+    /// * `deps` is the list of previously read nodes used in the condition.  These
+    ///   must be in this scope and must also be used elsewhere, since the condition
+    ///   only observes them.
+    ///
+    /// * `condition` takes the identifiers of `deps` and returns a `bool` expression,
+    ///   like `quote!(#version >= 3)`.
+    ///
+    /// When writing, an error is returned if whether the value is `Some` doesn't match
+    /// the condition.
+    pub fn conditional(
+        &self,
+        id: impl Into<String>,
+        deps: Vec<Node>,
+        condition: impl Fn(&Vec<Ident>) -> TokenStream + 'static,
+    ) -> (NodeOptional, Scope) {
+        let id = id.into();
+        for dep in &deps {
+            if dep.scope().0.id != self.0.id {
+                panic!("Conditional {} dependency {} is not in the same scope", id, dep.id());
+            }
+        }
+        let serial = self.seg(&id);
+        let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
+        let node = NodeOptional(Gc::new(NodeOptional_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            element: scope.clone(),
+            condition: OptionalCondition::Expr(Box::new(condition)),
+            condition_deps: deps,
+            mut_: GcCell::new(NodeOptionalMut_ {
+                serial_flag: None,
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        node.0.serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        scope.0.mut_.borrow_mut().escapable_parent = EscapableParent::Array(EscapableParentArray {
+            array: node.clone().into(),
+            element: scope.clone(),
+            parent: self.clone(),
+        });
+        return (node, scope);
+    }
+
+    /// Read/write an optional value (`Option` in Rust) at the end of the data, for
+    /// fields added in later versions of a format.  The value is `None` if there's no
+    /// more data when it would be read.  When writing, `None` values are omitted.
+    ///
+    /// Multiple trailing values can follow each other, but if one is `None` all the
+    /// following ones must be `None` too.  Trailing values must be in a top level
+    /// scope, after all other serial data.
+    pub fn trailing(&self, id: impl Into<String>) -> (NodeOptional, Scope) {
+        self.0.schema.0.borrow_mut().reader_bounds = ReaderBounds::Buffered;
+        let id = id.into();
+        if self.0.mut_.borrow().generate_config.is_none() {
+            panic!("Trailing value {} must be in a top level scope, where the end of the data is known", id);
+        }
+        let first_trailing = self.0.mut_.borrow_mut().trailing.take();
+        let serial = self.seg(&id);
+        self.0.mut_.borrow_mut().trailing = Some(first_trailing.unwrap_or_else(|| id.clone()));
+        let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
+        let node = NodeOptional(Gc::new(NodeOptional_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            element: scope.clone(),
+            condition: OptionalCondition::Eof,
+            condition_deps: vec![],
+            mut_: GcCell::new(NodeOptionalMut_ {
+                serial_flag: None,
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        node.0.serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        scope.0.mut_.borrow_mut().escapable_parent = EscapableParent::Array(EscapableParentArray {
+            array: node.clone().into(),
+            element: scope.clone(),
+            parent: self.clone(),
+        });
+        return (node, scope);
+    }
+
+    /// Store a checksum of other segments in this scope, as an integer of the
+    /// algorithm's size.  The covered segments are nodes that own a segment in this
+    /// scope, like `dynamic_bytes` or a range via `Range::node`.  They needn't be
+    /// adjacent and can come before or after the checksum; segments created after the
+    /// checksum can be added with `NodeChecksum::cover`.  The checksum is verified once
+    /// the scope is read and computed when writing, so it has no rust-side value.
+    pub fn checksum(
+        &self,
+        id: impl Into<String>,
+        algorithm: Checksum,
+        endian: Endian,
+        covered: Vec<Node>,
+    ) -> NodeChecksum {
+        let id = id.into();
+        let serial = self.seg(&id);
+        let node = NodeChecksum(Gc::new(NodeChecksum_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            algorithm: algorithm,
+            endian: endian,
+            mut_: GcCell::new(NodeChecksumMut_ { covered: vec![] }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        node.0.serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        for segment in covered {
+            node.cover(segment);
+        }
+        return node;
+    }
+
+    /// Read/write a value while capturing the raw bytes it was read from, for example
+    /// to verify a signature over the serialized data.  The value is read/written via
+    /// the returned scope.  The raw node is an `Option<Vec<u8>>` which is always
+    /// `Some` after reading; `write` controls whether it's used when writing.
+    pub fn capture(
+        &self,
+        id: impl Into<String>,
+        write: CaptureWrite,
+    ) -> (NodeCapture, NodeCaptureRaw, Scope) {
+        let id = id.into();
+        let serial = self.seg(&id);
+        let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
+        let raw_id = format!("{}__raw", id);
+        let raw = NodeCaptureRaw(Gc::new(NodeCaptureRaw_ {
+            scope: self.clone(),
+            id: raw_id.clone(),
+            id_ident: raw_id.ident().expect("Couldn't convert id into a rust identifier"),
+            mut_: GcCell::new(NodeCaptureRawMut_ {
+                capture: None,
+                rust: None,
+            }),
+        }));
+        let node = NodeCapture(Gc::new(NodeCapture_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            element: scope.clone(),
+            raw: raw.clone(),
+            write_mode: write,
+            mut_: GcCell::new(NodeCaptureMut_ { rust: None }),
+        }));
+        raw.0.mut_.borrow_mut().capture = Some(node.clone());
+        self.take_id(&id, Some(node.clone().into()));
+        self.take_id(&raw_id, Some(raw.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        node.0.serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        return (node, raw, scope);
+    }
+
+    /// Read/write a value from the decoded form of a byte node, for example a
+    /// compressed payload.  `serial` is a node with the Rust type `Vec<u8>`, like
+    /// `dynamic_bytes` or `remaining_bytes`.  The value is read/written via the
+    /// returned scope.  When writing, the data is encoded before being passed on to
+    /// `serial`, so any length depending on it is computed from the encoded data.
+    /// Reading fails if the decoded data is larger than `max_size` bytes.
+    pub fn decode(
+        &self,
+        id: impl Into<String>,
+        serial: impl Into<Node>,
+        codec: Codec,
+        max_size: usize,
+    ) -> (NodeDecode, Scope) {
+        let id = id.into();
+        let serial = serial.into();
+        let serial_type = serial.rust_type().to_string();
+        if serial_type != rust_type_bytes().to_string() {
+            panic!("Input {} to {} must be a byte vector, but it is {}", serial.id(), id, serial_type);
+        }
+        let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
+        let node = NodeDecode(Gc::new(NodeDecode_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            element: scope.clone(),
+            codec: codec,
+            max_size: max_size,
+            mut_: GcCell::new(NodeDecodeMut_ {
+                serial: None,
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.connect_value(&serial, node.clone().into(), &mut node.0.mut_.borrow_mut().serial);
+        return (node, scope);
+    }
+
+    /// Read/write a value from a byte-stuffed frame.  The frame is read up to the
+    /// framing's delimiter (skipping any empty frames), un-stuffed, and the value is
+    /// read from the result via the returned scope.  When writing, the serialized value
+    /// is stuffed and followed by the delimiter.  Since empty frames are skipped, SLIP
+    /// and HDLC frames must contain at least one byte.
+    pub fn framed(&self, id: impl Into<String>, framing: Framing) -> (NodeFramed, Scope) {
+        self.0.schema.0.borrow_mut().reader_bounds = ReaderBounds::Buffered;
+        let id = id.into();
+        let serial = self.seg(&id);
+        let scope = Scope::new(&format!("{}__scope", id), &self.0.schema, None);
+        let node = NodeFramed(Gc::new(NodeFramed_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            element: scope.clone(),
+            framing: framing,
+            mut_: GcCell::new(NodeFramedMut_ { rust: None }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        node.0.serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
         return (node, scope);
     }
 
+    /// Read/write a sequence of type-length-value entries.  The Rust value is a `Vec`
+    /// of the enum `type_name`, with a variant for each type defined with
+    /// `NodeTlv::known` plus `Unknown(type, Vec<u8>)` for other types, so unknown
+    /// entries are written back unchanged.
+    pub fn tlv(&self, id: impl Into<String>, type_name: impl Into<String>, config: TlvConfig) -> NodeTlv {
+        let id = id.into();
+        let type_name = type_name.into();
+        if tlv_int_type(config.type_bytes).is_none() || tlv_int_type(config.len_bytes).is_none() {
+            panic!("Type and length sizes of {} must be 1, 2, 4, or 8 bytes", id);
+        }
+        let len;
+        let end;
+        match config.end {
+            TlvEnd::Eof => {
+                self.0.schema.0.borrow_mut().reader_bounds = ReaderBounds::Buffered;
+                len = None;
+                end = TlvEnd_::Eof;
+            },
+            TlvEnd::Len(l) => {
+                check_int(&id, &l);
+                len = Some(l);
+                end = TlvEnd_::Len;
+            },
+            TlvEnd::Terminator(t) => {
+                len = None;
+                end = TlvEnd_::Terminator(t);
+            },
+        }
+        let serial = self.seg(&id);
+        let node = NodeTlv(Gc::new(NodeTlv_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            type_name: type_name.clone(),
+            type_name_ident: type_name.ident().expect("Couldn't convert type name into a rust identifier"),
+            serial_before: self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned(),
+            serial: serial,
+            type_bytes: config.type_bytes,
+            len_bytes: config.len_bytes,
+            endian: config.endian,
+            length_first: config.length_first,
+            len_includes_type: config.len_includes_type,
+            end: end,
+            mut_: GcCell::new(NodeTlvMut_ {
+                serial_len: None,
+                variants: vec![],
+                rust: None,
+                type_attrs: vec![],
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+        node.0.serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        if self.0.schema.0.borrow_mut().tlvs.insert(type_name.clone(), node.clone()).is_some() {
+            panic!("TLV type name {} is already used", type_name);
+        }
+        if let Some(len) = len {
+            self.connect_value(&len, node.clone().into(), &mut node.0.mut_.borrow_mut().serial_len);
+        }
+        return node;
+    }
+
     /// Inject a custom node.
     ///
     /// * `rust_type` is the end result of the read.
@@ -555,7 +1393,9 @@ impl Scope {
     ///   inputs and stores it in the output node identifier.
     ///
     /// * `write_code` does the opposite, taking the Rust value and producing values for
-    ///   each input node.
+    ///   each input node.  It can fail with `.errorize_write(id)?` (from
+    ///   `inarybay_runtime::error::WriteErrCtx`) on a `Result`, which returns a
+    ///   `WriteError` for the node from the write function.
     ///
     /// See `string_utf8` for an example.
     pub fn custom(
@@ -583,12 +1423,49 @@ impl Scope {
         self.take_id(&id, Some(node.clone().into()));
         for arg in serial {
             node.0.mut_.borrow_mut().serial.push(None);
-            self.lift_connect(
-                &self.get_ancestry_to(&arg),
-                &arg,
-                node.clone().into(),
-                node.0.mut_.borrow_mut().serial.last_mut().unwrap(),
-            );
+            self.connect_value(&arg, node.clone().into(), node.0.mut_.borrow_mut().serial.last_mut().unwrap());
+        }
+        return node;
+    }
+
+    /// Create a node of a kind defined outside this crate (see `Plugin`).  `inputs`
+    /// are values the node is produced from when reading, and which it produces when
+    /// writing, like `custom`.  If the plugin has a serial segment it's placed after
+    /// any previous segments in this scope.
+    pub fn plugin(&self, id: impl Into<String>, plugin: impl Plugin + 'static, inputs: Vec<Node>) -> NodePlugin {
+        let id = id.into();
+        let rust_type = plugin.rust_type();
+        syn::parse2::<Path>(rust_type.clone()).expect("Rust type isn't a valid type");
+        let serial_before;
+        let serial;
+        if plugin.has_serial() {
+            serial_before = self.0.serial_root.0.mut_.borrow().sub_segments.last().cloned();
+            serial = Some(self.seg(&id));
+        } else {
+            serial_before = None;
+            serial = None;
+        }
+        let node = NodePlugin(Gc::new(NodePlugin_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            rust_type: rust_type,
+            plugin: Box::new(plugin),
+            serial_before: serial_before,
+            serial: serial.clone(),
+            mut_: GcCell::new(NodePluginMut_ {
+                inputs: vec![],
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        if let Some(serial) = serial {
+            self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
+            serial.0.mut_.borrow_mut().rust = Some(node.clone().into());
+        }
+        for input in inputs {
+            node.0.mut_.borrow_mut().inputs.push(None);
+            self.connect_value(&input, node.clone().into(), node.0.mut_.borrow_mut().inputs.last_mut().unwrap());
         }
         return node;
     }
@@ -699,13 +1576,8 @@ impl Scope {
             expect: value,
             mut_: GcCell::new(NodeConstMut_ { serial: None }),
         }));
-        self.0.mut_.borrow_mut().rust_extra_roots.push(rust.clone());
-        self.lift_connect(
-            &self.get_ancestry_to(&serial),
-            &serial,
-            rust.clone().into(),
-            &mut rust.0.mut_.borrow_mut().serial,
-        );
+        self.0.mut_.borrow_mut().rust_extra_roots.push(rust.clone().into());
+        self.connect_value(&serial, rust.clone().into(), &mut rust.0.mut_.borrow_mut().serial);
     }
 
     /// Read/write a rust struct.
@@ -758,12 +1630,7 @@ impl Scope {
         self.take_id(&id, Some(node.clone().into()));
         self.0.serial_root.0.mut_.borrow_mut().sub_segments.push(node.clone().into());
         self.0.schema.0.as_ref().borrow_mut().enums.entry(enum_name).or_insert_with(Vec::new).push(node.clone());
-        self.lift_connect(
-            &self.get_ancestry_to(&tag),
-            &tag,
-            node.clone().into(),
-            &mut node.0.mut_.borrow_mut().serial_tag,
-        );
+        self.connect_value(&tag, node.clone().into(), &mut node.0.mut_.borrow_mut().serial_tag);
         return node;
     }
 
@@ -796,11 +1663,33 @@ impl Scope {
                 EscapableParent::Enum(e) => {
                     at = e.parent.clone();
                 },
+                EscapableParent::Array(a) => {
+                    at = a.parent.clone();
+                },
             };
         }
         self.0.mut_.borrow_mut().level_ids.insert(id.clone(), node);
     }
 
+    /// Whether `node`'s value is known when writing: it's consumed on the rust side (an
+    /// object field, element, or the scope root itself) or is supplied by the
+    /// surrounding code (params, array indices, previous elements).
+    pub(crate) fn has_rust_consumer(&self, node: &Node) -> bool {
+        if matches!(&node.0, Node_::Param(_) | Node_::ArrayIndex(_) | Node_::Previous(_)) {
+            return true;
+        }
+        let mut_ = self.0.mut_.borrow();
+        if mut_.rust_connected.get(&node.id()).map(|c| !c.is_empty()).unwrap_or(false) {
+            return true;
+        }
+        if let Some(root) = &mut_.rust_root {
+            if root.id() == node.id() {
+                return true;
+            }
+        }
+        return false;
+    }
+
     pub(crate) fn get_rust_root(&self) -> Node {
         return self
             .0
@@ -813,6 +1702,9 @@ impl Scope {
     }
 
     fn seg(&self, id: &str) -> NodeSerialSegment {
+        if let Some(trailing) = &self.0.mut_.borrow().trailing {
+            panic!("{} can't follow trailing value {}, trailing values must be at the end of the data", id, trailing);
+        }
         let id = format!("{}__serial_seg", id);
         let node = NodeSerialSegment(Gc::new(NodeSerialSegment_ {
             scope: self.clone(),
@@ -820,7 +1712,10 @@ impl Scope {
             id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
             serial_root: self.0.serial_root.clone().into(),
             serial_before: self.0.serial_root.0.mut_.borrow().segments.last().cloned(),
-            mut_: GcCell::new(NodeSerialSegmentMut_ { rust: None }),
+            mut_: GcCell::new(NodeSerialSegmentMut_ {
+                rust: None,
+                covered_by: vec![],
+            }),
         }));
         self.take_id(&id, Some(node.clone().into()));
         self.0.serial_root.0.mut_.borrow_mut().segments.push(node.clone());
@@ -838,7 +1733,7 @@ impl Scope {
             match &nesting_parent {
                 EscapableParent::None => {
                     panic!(
-                        "Serial-side dependency {} is not from any containing scope; maybe this is within an array context and so can't depend on higher scopes",
+                        "Serial-side dependency {} is not from any containing scope; maybe this is within a nested context that can't depend on higher scopes",
                         serial.id()
                     );
                 },
@@ -846,6 +1741,10 @@ impl Scope {
                     ancestry.push(SomeEscapableParent::Enum(e.clone()));
                     at = e.parent.clone();
                 },
+                EscapableParent::Array(a) => {
+                    ancestry.push(SomeEscapableParent::Array(a.clone()));
+                    at = a.parent.clone();
+                },
             };
         }
         ancestry.reverse();
@@ -917,6 +1816,12 @@ impl Scope {
                         },
                     }
                 },
+                SomeEscapableParent::Array(ancestor_array) => {
+                    panic!(
+                        "Ranges can't be shared across elements of array {}",
+                        ancestor_array.array.id()
+                    );
+                },
             }
         }
         let mut range_level = range_level.borrow_mut();
@@ -940,6 +1845,145 @@ impl Scope {
         }
     }
 
+    /// Allow `value`, from this scope or an enclosing scope, to be observed (read but
+    /// not connected) from this scope.
+    pub(crate) fn observe(&self, value: &Node) {
+        let value_scope_id = value.scope().0.id.clone();
+        let mut at = self.clone();
+        let mut outermost = None;
+        loop {
+            if at.0.id == value_scope_id {
+                break;
+            }
+            let parent = match &at.0.mut_.borrow().escapable_parent {
+                EscapableParent::None => {
+                    panic!("Observed value {} is not from any containing scope", value.id());
+                },
+                EscapableParent::Enum(e) => e.parent.clone(),
+                EscapableParent::Array(a) => a.parent.clone(),
+            };
+            outermost = Some(at);
+            at = parent;
+        }
+        if let Some(element) = outermost {
+            element.0.mut_.borrow_mut().observed_externals.push(value.clone());
+        }
+    }
+
+    /// Get the copy of `serial` (from outside the array) in this array element scope,
+    /// creating it if necessary.
+    fn array_external(&self, array: &Node, serial: &Node) -> NodeArrayExternal {
+        let id = format!("{}__outer", serial.id());
+        for external in &self.0.mut_.borrow().array_externals {
+            if external.0.id == id {
+                return external.clone();
+            }
+        }
+        let node = NodeArrayExternal(Gc::new(NodeArrayExternal_ {
+            scope: self.clone(),
+            id: id.clone(),
+            id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+            mut_: GcCell::new(NodeArrayExternalMut_ {
+                outer: None,
+                rust: None,
+            }),
+        }));
+        self.take_id(&id, Some(node.clone().into()));
+        self.take_id(&format!("{}__lifted", id), None);
+        array.scope().connect_value_ext(serial, array.clone(), &mut node.0.mut_.borrow_mut().outer, true);
+        let mut self_mut = self.0.mut_.borrow_mut();
+
+        // Visited when writing to lift the value out of the element
+        self_mut.serial_extra_roots.push(node.clone().into());
+        self_mut.array_externals.push(node.clone());
+        return node;
+    }
+
+    /// Connect a value node to a consumer, lifting it out of nested scopes as
+    /// necessary.  If the value already has a consumer and the new consumer is in the
+    /// same scope, the new consumer gets a copy of the value (`NodeFanOut`) which is
+    /// checked against the original when writing.  Values from outside an array are
+    /// copied into the element scope (`NodeArrayExternal`) first.
+    pub(crate) fn connect_value(&self, serial: &Node, rust: Node, rust_field: &mut LateInit<RedirectRef<Node, Node>>) {
+        self.connect_value_ext(serial, rust, rust_field, false);
+    }
+
+    /// Like `connect_value`.  If `array_use` the consumer is an array using the value
+    /// in its elements, which always gets a copy so another consumer provides the
+    /// value if the array is empty when writing.
+    fn connect_value_ext(
+        &self,
+        serial: &Node,
+        rust: Node,
+        rust_field: &mut LateInit<RedirectRef<Node, Node>>,
+        array_use: bool,
+    ) {
+        let ancestry = self.get_ancestry_to(serial);
+        for level in &ancestry {
+            let SomeEscapableParent::Array(level) = level else {
+                continue;
+            };
+            let external = level.element.array_external(&level.array, serial);
+            self.connect_value(&external.into(), rust, rust_field);
+            return;
+        }
+        let serial_scope = serial.scope();
+
+        // Values assigned before each element in both directions can be shared directly
+        if matches!(&serial.0, Node_::Previous(_) | Node_::ArrayIndex(_)) {
+            self.lift_connect(&ancestry, serial, rust, rust_field);
+            return;
+        }
+
+        // Only one variant is written, so consumers within the same enum share a
+        // connection
+        let enum_id = match ancestry.first() {
+            Some(SomeEscapableParent::Enum(level)) => Some(level.enum_.0.id.clone()),
+            _ => None,
+        };
+        let index;
+        let primary_taken;
+        {
+            let mut serial_scope_mut = serial_scope.0.mut_.borrow_mut();
+            let uses = serial_scope_mut.rust_connected.entry(serial.id()).or_default();
+            if enum_id.is_some() {
+                if let Some((_, _, node)) = uses.iter().find(|(e, _, _)| *e == enum_id) {
+                    let node = node.clone();
+                    drop(serial_scope_mut);
+                    self.lift_connect(&ancestry, &node, rust, rust_field);
+                    return;
+                }
+            }
+            index = uses.len();
+            primary_taken = uses.iter().any(|(_, _, n)| n.id() == serial.id());
+        }
+        let node: Node;
+        if !array_use && !primary_taken {
+            node = serial.clone();
+        } else {
+            let id = format!("{}__use{}", serial.id(), index);
+            let fan_out = NodeFanOut(Gc::new(NodeFanOut_ {
+                scope: serial_scope.clone(),
+                id: id.clone(),
+                id_ident: id.ident().expect("Couldn't convert id into a rust identifier"),
+                serial: serial.clone(),
+                mut_: GcCell::new(NodeFanOutMut_ { rust: None }),
+                rust_type: serial.rust_type(),
+            }));
+            serial_scope.take_id(&id, Some(fan_out.clone().into()));
+
+            // Visited first when writing so mismatches are caught before anything is
+            // written
+            serial_scope.0.mut_.borrow_mut().serial_extra_roots.push(fan_out.clone().into());
+            node = fan_out.into();
+        }
+        serial_scope.0.mut_.borrow_mut().rust_connected
+            .get_mut(&serial.id())
+            .unwrap()
+            .push((enum_id, array_use, node.clone()));
+        self.lift_connect(&ancestry, &node, rust, rust_field);
+    }
+
     pub(crate) fn lift_connect<
         T: Clone + NodeMethods + Into<Node> + Trace + Finalize,
     >(
@@ -981,6 +2025,7 @@ impl Scope {
                             level.parent.0.mut_.borrow_mut().has_external_deps = true;
                         }
                     },
+                    SomeEscapableParent::Array(_) => unreachable!(),
                 }
             }
 
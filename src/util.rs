@@ -12,8 +12,10 @@ use gc::{
     Finalize,
 };
 use proc_macro2::{
+    Group,
     Ident,
     TokenStream,
+    TokenTree,
 };
 use quote::{
     format_ident,
@@ -206,10 +208,104 @@ pub(crate) fn generate_basic_write(
     };
 }
 
+/// Replace all uses of an identifier in generated code.
+pub(crate) fn rename_ident(code: TokenStream, from: &Ident, to: &Ident) -> TokenStream {
+    return code.into_iter().map(|t| match t {
+        TokenTree::Ident(i) if i == *from => TokenTree::Ident(to.clone()),
+        TokenTree::Group(g) => {
+            let mut out = Group::new(g.delimiter(), rename_ident(g.stream(), from, to));
+            out.set_span(g.span());
+            TokenTree::Group(out)
+        },
+        t => t,
+    }).collect();
+}
+
+/// Convert a type name like `TreeNode` to `tree_node`, for function names.
+pub(crate) fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if prev_lower {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+            prev_lower = false;
+        } else {
+            out.push(c);
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        }
+    }
+    return out;
+}
+
 pub(crate) fn offset_ident() -> Ident {
     return "offset".ident().unwrap();
 }
 
+/// True if a stringified rust type is one of the primitive integer types.
+pub(crate) fn is_int_type(rust_type: &str) -> bool {
+    return [
+        "u8",
+        "u16",
+        "u32",
+        "u64",
+        "u128",
+        "usize",
+        "i8",
+        "i16",
+        "i32",
+        "i64",
+        "i128",
+        "isize",
+    ].contains(&rust_type);
+}
+
+fn is_signed_int_type(rust_type: &str) -> bool {
+    return is_int_type(rust_type) && rust_type.starts_with('i');
+}
+
+/// An expression converting a length/count value of integer type `len_type` to
+/// `usize` when reading, returning a read error for `node` if it's negative.
+pub(crate) fn generate_read_len(gen_ctx: &GenerateContext, node: &str, len: &Ident, len_type: &str) -> TokenStream {
+    if len_type == "usize" {
+        return quote!(#len);
+    }
+    if !is_signed_int_type(len_type) {
+        return quote!(#len as usize);
+    }
+    let err = gen_ctx.new_read_err(node, "Negative length", quote!(format!("Negative length {}", #len)));
+    return quote!(usize:: try_from(#len).map_err(|_| #err) ?);
+}
+
+/// Like `generate_read_len` but when writing, where `len` comes from the rust side.
+pub(crate) fn generate_write_len(gen_ctx: &GenerateContext, node: &str, len: &Ident, len_type: &str) -> TokenStream {
+    if len_type == "usize" {
+        return quote!(#len);
+    }
+    if !is_signed_int_type(len_type) {
+        return quote!(#len as usize);
+    }
+    let err = gen_ctx.new_write_err(node, "Negative length", quote!(format!("Negative length {}", #len)));
+    return quote!(usize:: try_from(#len).map_err(|_| #err) ?);
+}
+
+/// The largest positive value of an integer type (see `is_int_type`), limited to
+/// `u64`.
+pub(crate) fn int_type_max(rust_type: &str) -> u64 {
+    match rust_type {
+        "u8" => return u8::MAX as u64,
+        "u16" => return u16::MAX as u64,
+        "u32" => return u32::MAX as u64,
+        "i8" => return i8::MAX as u64,
+        "i16" => return i16::MAX as u64,
+        "i32" => return i32::MAX as u64,
+        "i64" | "isize" => return i64::MAX as u64,
+        _ => return u64::MAX,
+    }
+}
+
 pub(crate) fn rust_type_bytes() -> TokenStream {
     return quote!(std:: vec:: Vec < u8 >);
 }